@@ -1,65 +1,185 @@
 use crate::utils::*;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 type Input = Vec<i64>;
 type Output = i64;
 
 fn input_transformer(input: &str) -> Input {
-    let mut input: Vec<i64> = parse_input(input);
-    input.sort_unstable();
-    input
+    parse_input(input)
 }
 
 fn solve_part1(input: Input) -> Output {
-    let (a, b) = sum2n(&input, 2020).unwrap();
-    input[a] * input[b]
+    k_sum(&input, 2, 2020)
+        .unwrap()
+        .iter()
+        .map(|&i| input[i])
+        .product()
 }
 
 fn solve_part2(input: Input) -> Output {
-    let (a, b, c) = sum3n(&input, 2020).unwrap();
-    input[a] * input[b] * input[c]
+    k_sum(&input, 3, 2020)
+        .unwrap()
+        .iter()
+        .map(|&i| input[i])
+        .product()
 }
 
-/// Find two numbers that add to the `target_sum`
+/// Inputs from this size on find pairs by hashing instead of sorting
+const HASHING_THRESHOLD: usize = 256;
+
+/// Find `k` numbers at distinct indices that add to the `target_sum`
 ///
-/// Assumes sorted `input`
+/// Returns the indices in ascending order
+pub fn k_sum(input: &[i64], k: usize, target_sum: i64) -> Option<Vec<usize>> {
+    k_sums(input, k, target_sum).next()
+}
+
+/// Find all combinations of `k` distinct indices whose numbers add to the
+/// `target_sum`, each combination once with indices in ascending order
 ///
-/// Time:   O(n)
-/// Space:  O(1)
-fn sum2n(input: &[i64], target_sum: i64) -> Option<(usize, usize)> {
-    let mut l = 0;
-    let mut r = input.len() - 1;
-    while l < r {
-        match input[l] + input[r] {
-            sum if sum == target_sum => return Some((l, r)),
-            sum if sum < target_sum => l += 1,
-            _ => r -= 1,
-        }
-    }
+/// Pairs are found by hashing for large inputs, otherwise the input is sorted
+/// and scanned with two pointers, which is also used below every fixed number
+/// for `k > 2`. Combinations are found lazily, so taking the first one stops
+/// the search early
+///
+/// Time:   O(n + combinations) for `k = 2` with hashing, else
+///         O(n log n + n^(k-1) + combinations)
+/// Space:  O(n) for `k <= 2`, else O(k n) for the indices left at each fixed
+///         number
+pub fn k_sums(input: &[i64], k: usize, target_sum: i64) -> impl Iterator<Item = Vec<usize>> + '_ {
+    let sums: Box<dyn Iterator<Item = Vec<usize>>> = if k == 2 && input.len() >= HASHING_THRESHOLD {
+        let indices = (0..input.len()).collect();
+        Box::new(sum2n_hashing(input, indices, target_sum as i128))
+    } else {
+        let mut sorted: Vec<usize> = (0..input.len()).collect();
+        sorted.sort_by_key(|&i| input[i]);
+        Box::new(sumkn(input, sorted, k, target_sum as i128))
+    };
 
-    None
+    sums.map(|mut c| {
+        c.sort_unstable();
+        c
+    })
 }
 
-/// Find three numbers that add to the `target_sum`
-///
-/// Assumes sorted `input`
+/// Find all `k` numbers among `indices`, sorted by value, that add to the
+/// `target_sum`, fixing the smallest number and recursing for `k > 2`
+fn sumkn(
+    input: &[i64],
+    indices: Vec<usize>,
+    k: usize,
+    target_sum: i128,
+) -> Box<dyn Iterator<Item = Vec<usize>> + '_> {
+    match k {
+        0 if target_sum == 0 => Box::new(std::iter::once(vec![])),
+        0 => Box::new(std::iter::empty()),
+        1 => Box::new(
+            indices
+                .into_iter()
+                .filter(move |&i| input[i] as i128 == target_sum)
+                .map(|i| vec![i]),
+        ),
+        2 => Box::new(sum2n_two_pointer(input, indices, target_sum)),
+        _ => Box::new((0..indices.len()).flat_map(move |p| {
+            let i = indices[p];
+            sumkn(
+                input,
+                indices[p + 1..].to_vec(),
+                k - 1,
+                target_sum - input[i] as i128,
+            )
+            .map(move |mut c| {
+                c.push(i);
+                c
+            })
+        })),
+    }
+}
+
+/// Find all pairs among `indices`, sorted by value, that add to the `target_sum`,
+/// scanning runs of equal numbers from both ends as pairs are requested
 ///
-/// Time:   O(n^2)
-/// Space:  O(1)
-fn sum3n(input: &[i64], target_sum: i64) -> Option<(usize, usize, usize)> {
-    for k in 0..input.len() {
-        match sum2n(input, target_sum - input[k]) {
-            Some((i, j)) => return Some((i, j, k)),
-            _ => continue,
+/// Time:   O(n + pairs)
+/// Space:  O(1) besides the `indices`
+fn sum2n_two_pointer(
+    input: &[i64],
+    indices: Vec<usize>,
+    target_sum: i128,
+) -> impl Iterator<Item = Vec<usize>> + '_ {
+    let indices: Rc<[usize]> = indices.into();
+    let value = {
+        let indices = indices.clone();
+        move |p: usize| input[indices[p]] as i128
+    };
+
+    // Ranges of positions whose numbers pair up, the second one after the first
+    let (mut l, mut r) = (0, indices.len().saturating_sub(1));
+    let runs = std::iter::from_fn(move || {
+        while l < r {
+            match value(l) + value(r) {
+                sum if sum < target_sum => l += 1,
+                sum if sum > target_sum => r -= 1,
+                // Every pair within a run of equal numbers matches
+                _ if value(l) == value(r) => {
+                    let run = (l..r + 1, l..r + 1);
+                    l = r;
+                    return Some(run);
+                }
+                _ => {
+                    let (mut l_end, mut r_start) = (l, r);
+                    while value(l_end) == value(l) {
+                        l_end += 1;
+                    }
+                    while value(r_start - 1) == value(r) {
+                        r_start -= 1;
+                    }
+                    let runs = (l..l_end, r_start..r + 1);
+                    l = l_end;
+                    r = r_start - 1;
+                    return Some(runs);
+                }
+            }
         }
-    }
+        None
+    });
 
-    None
+    runs.flat_map(move |(a_run, b_run)| {
+        let indices = indices.clone();
+        a_run.flat_map(move |a| {
+            let indices = indices.clone();
+            b_run
+                .clone()
+                .filter(move |&b| b > a)
+                .map(move |b| vec![indices[a], indices[b]])
+        })
+    })
+}
+
+/// Find all pairs among `indices` that add to the `target_sum`, each pair as
+/// soon as its second number is reached
+///
+/// Time:   O(n + pairs)
+/// Space:  O(n)
+fn sum2n_hashing(
+    input: &[i64],
+    indices: Vec<usize>,
+    target_sum: i128,
+) -> impl Iterator<Item = Vec<usize>> + '_ {
+    let mut seen: HashMap<i128, Vec<usize>> = HashMap::new();
+    indices.into_iter().flat_map(move |j| {
+        let value = input[j] as i128;
+        let matches = seen.get(&(target_sum - value)).cloned().unwrap_or_default();
+        seen.entry(value).or_default().push(j);
+        matches.into_iter().map(move |i| vec![i, j])
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2};
+    use super::{input_transformer, k_sum, k_sums, solve_part1, solve_part2};
     use crate::utils::*;
+    use itertools::Itertools;
 
     const DAY: u32 = 1;
     const SAMPLE: &str = "1721
@@ -98,4 +218,65 @@ mod tests {
 
         assert_eq!(155806250, solution);
     }
+
+    #[test]
+    fn test_k_sum_distinct_indices() {
+        // 1010 appears once, so it can not be paired with itself
+        assert_eq!(None, k_sum(&[1010, 5, 7], 2, 2020));
+        assert_eq!(Some(vec![0, 2]), k_sum(&[1010, 5, 1010], 2, 2020));
+        assert_eq!(None, k_sum(&[1000, 10, 5], 3, 2010));
+        assert_eq!(Some(vec![1, 2, 3]), k_sum(&[9, -4, 10, 0], 3, 6));
+    }
+
+    #[test]
+    fn test_k_sum_stops_at_first() {
+        // Every pair and triple matches, but only the first one is searched for
+        let input = vec![1010; 6000];
+        assert_eq!(Some(vec![0, 1]), k_sum(&input, 2, 2020));
+        assert_eq!(Some(vec![0, 1]), k_sum(&input[..100], 2, 2020));
+        assert_eq!(Some(vec![0, 1, 2]), k_sum(&input, 3, 3030));
+    }
+
+    #[test]
+    fn test_k_sums_all_solutions() {
+        let input = [3, 1, 3, -1, 5, 3, 2];
+
+        let pairs = k_sums(&input, 2, 6).sorted().collect_vec();
+        assert_eq!(vec![vec![0, 2], vec![0, 5], vec![1, 4], vec![2, 5]], pairs);
+
+        let brute = (0..input.len())
+            .combinations(3)
+            .filter(|c| c.iter().map(|&i| input[i]).sum::<i64>() == 7)
+            .collect_vec();
+        assert_eq!(brute, k_sums(&input, 3, 7).sorted().collect_vec());
+        assert_eq!(
+            vec![Vec::<usize>::new()],
+            k_sums(&input, 0, 0).collect_vec()
+        );
+    }
+
+    #[test]
+    fn test_k_sums_strategies_agree() {
+        let input = (0..600).map(|i| (i * 37) % 101 - 50).collect_vec();
+        let brute = |k: usize, input: &[i64], target: i64| {
+            (0..input.len())
+                .combinations(k)
+                .filter(|c| c.iter().map(|&i| input[i]).sum::<i64>() == target)
+                .collect_vec()
+        };
+
+        // Large enough to find pairs by hashing
+        assert_eq!(
+            brute(2, &input, 30),
+            k_sums(&input, 2, 30).sorted().collect_vec()
+        );
+        assert_eq!(
+            brute(2, &input[..50], 30),
+            k_sums(&input[..50], 2, 30).sorted().collect_vec()
+        );
+        assert_eq!(
+            brute(3, &input[..80], -7),
+            k_sums(&input[..80], 3, -7).sorted().collect_vec()
+        );
+    }
 }
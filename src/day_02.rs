@@ -1,39 +1,339 @@
 use crate::utils::*;
 
+use itertools::Itertools;
 use regex::Regex;
+use serde::Serialize;
 use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
 
 type Input = Vec<PasswordEntry>;
 type Output = usize;
 
-fn input_transformer(input: &str) -> Input {
-    parse_input(input)
+fn input_transformer(input: &str) -> Result<Input> {
+    input.lines().map(|l| l.trim().parse()).collect()
 }
 
 fn solve_part1(input: Input) -> Output {
     input
         .iter()
-        .filter(|p| {
-            let count = p.password.chars().filter(|c| c == &p.policy_char).count();
-
-            p.policy_lower_bound <= count && count <= p.policy_upper_bound
-        })
+        .filter(|p| p.count_policy().check(&p.password))
         .count()
 }
 
 fn solve_part2(input: Input) -> Output {
     input
         .iter()
-        .filter(|p| {
-            let c1 = p.password.chars().nth(p.policy_lower_bound - 1).unwrap();
-            let c2 = p.password.chars().nth(p.policy_upper_bound - 1).unwrap();
-
-            (c1 == p.policy_char) ^ (c2 == p.policy_char)
-        })
+        .filter(|p| p.position_policy().check(&p.password))
         .count()
 }
 
+/// Counts the passwords which satisfy the `policy`.
+pub fn count_valid<'a, I>(passwords: I, policy: &dyn Policy) -> usize
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    passwords.into_iter().filter(|p| policy.check(p)).count()
+}
+
+/// A password rule. `Display` gives the rule in the syntax of `parse_policy`.
+pub trait Policy: fmt::Debug + fmt::Display {
+    /// Why the password breaks the rule, `None` when it satisfies it.
+    fn violation(&self, password: &str) -> Option<String>;
+
+    fn check(&self, password: &str) -> bool {
+        self.violation(password).is_none()
+    }
+}
+
+/// The character appears between `lo` and `hi` times, both inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountInRange {
+    pub c: char,
+    pub lo: usize,
+    pub hi: usize,
+}
+
+impl Policy for CountInRange {
+    fn violation(&self, password: &str) -> Option<String> {
+        let count = password.chars().filter(|c| *c == self.c).count();
+        if self.lo <= count && count <= self.hi {
+            None
+        } else {
+            Some(format!(
+                "char '{}' appears {} times, allowed {}-{}",
+                self.c, count, self.lo, self.hi
+            ))
+        }
+    }
+}
+
+impl fmt::Display for CountInRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "count('{}', {}, {})", self.c, self.lo, self.hi)
+    }
+}
+
+/// The character is at exactly one of the two 1-based positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExactlyOnePosition {
+    pub c: char,
+    pub first: usize,
+    pub second: usize,
+}
+
+impl Policy for ExactlyOnePosition {
+    fn violation(&self, password: &str) -> Option<String> {
+        let at = |position: usize| {
+            position
+                .checked_sub(1)
+                .and_then(|i| password.chars().nth(i))
+                == Some(self.c)
+        };
+        match (at(self.first), at(self.second)) {
+            (true, true) => Some(format!(
+                "char '{}' is at both positions {} and {}",
+                self.c, self.first, self.second
+            )),
+            (false, false) => Some(format!(
+                "char '{}' is at neither position {} nor {}",
+                self.c, self.first, self.second
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExactlyOnePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "position('{}', {}, {})", self.c, self.first, self.second)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinLength(pub usize);
+
+impl Policy for MinLength {
+    fn violation(&self, password: &str) -> Option<String> {
+        let length = password.chars().count();
+        if length >= self.0 {
+            None
+        } else {
+            Some(format!(
+                "length is {}, required at least {}",
+                length, self.0
+            ))
+        }
+    }
+}
+
+impl fmt::Display for MinLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "min_length({})", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Lower => c.is_lowercase(),
+            CharClass::Upper => c.is_uppercase(),
+            CharClass::Digit => c.is_ascii_digit(),
+            CharClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CharClass::Lower => write!(f, "lower"),
+            CharClass::Upper => write!(f, "upper"),
+            CharClass::Digit => write!(f, "digit"),
+            CharClass::Symbol => write!(f, "symbol"),
+        }
+    }
+}
+
+/// Every class is present at least once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequiredClasses(pub Vec<CharClass>);
+
+impl Policy for RequiredClasses {
+    fn violation(&self, password: &str) -> Option<String> {
+        let missing = self
+            .0
+            .iter()
+            .filter(|class| !password.chars().any(|c| class.contains(c)))
+            .join(", ");
+        if missing.is_empty() {
+            None
+        } else {
+            Some(format!("missing {}", missing))
+        }
+    }
+}
+
+impl fmt::Display for RequiredClasses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "requires({})", self.0.iter().join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForbiddenSubstring(pub String);
+
+impl Policy for ForbiddenSubstring {
+    fn violation(&self, password: &str) -> Option<String> {
+        if password.contains(&self.0) {
+            Some(format!("contains forbidden \"{}\"", self.0))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ForbiddenSubstring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "forbids(\"{}\")", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct And(pub Box<dyn Policy>, pub Box<dyn Policy>);
+
+impl Policy for And {
+    fn violation(&self, password: &str) -> Option<String> {
+        match (self.0.violation(password), self.1.violation(password)) {
+            (Some(a), Some(b)) => Some(format!("{}; {}", a, b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+impl fmt::Display for And {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} and {})", self.0, self.1)
+    }
+}
+
+#[derive(Debug)]
+pub struct Or(pub Box<dyn Policy>, pub Box<dyn Policy>);
+
+impl Policy for Or {
+    fn violation(&self, password: &str) -> Option<String> {
+        let a = self.0.violation(password)?;
+        let b = self.1.violation(password)?;
+        Some(format!("{}; {}", a, b))
+    }
+}
+
+impl fmt::Display for Or {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} or {})", self.0, self.1)
+    }
+}
+
+#[derive(Debug)]
+pub struct Xor(pub Box<dyn Policy>, pub Box<dyn Policy>);
+
+impl Policy for Xor {
+    fn violation(&self, password: &str) -> Option<String> {
+        match (self.0.violation(password), self.1.violation(password)) {
+            (None, None) => Some(format!("both {} and {} hold", self.0, self.1)),
+            (Some(a), Some(b)) => Some(format!("{}; {}", a, b)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Xor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} xor {})", self.0, self.1)
+    }
+}
+
+#[derive(Debug)]
+pub struct Not(pub Box<dyn Policy>);
+
+impl Policy for Not {
+    fn violation(&self, password: &str) -> Option<String> {
+        match self.0.violation(password) {
+            Some(_) => None,
+            None => Some(format!("{} holds", self.0)),
+        }
+    }
+}
+
+impl fmt::Display for Not {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not {}", self.0)
+    }
+}
+
+peg::parser! {
+    grammar policy_parser() for str {
+        rule _() = quiet!{[' ' | '\t' | '\r' | '\n']*}
+
+        rule number() -> usize
+          = n:$(['0'..='9']+) {? n.parse().or(Err("number")) }
+
+        rule character() -> char = "'" !"'" c:$([_]) "'" { c.chars().next().unwrap() }
+
+        rule string() -> String = "\"" s:$((!"\"" [_])*) "\"" { s.to_owned() }
+
+        rule class() -> CharClass
+          = "lower" { CharClass::Lower }
+          / "upper" { CharClass::Upper }
+          / "digit" { CharClass::Digit }
+          / "symbol" { CharClass::Symbol }
+
+        rule atom() -> Box<dyn Policy>
+          = "count" _ "(" _ c:character() _ "," _ lo:number() _ "," _ hi:number() _ ")" {
+              Box::new(CountInRange { c, lo, hi })
+          }
+          / "position" _ "(" _ c:character() _ "," _ first:number() _ "," _ second:number() _ ")" {
+              Box::new(ExactlyOnePosition { c, first, second })
+          }
+          / "min_length" _ "(" _ n:number() _ ")" { Box::new(MinLength(n)) }
+          / "requires" _ "(" _ c:(class() ++ (_ "," _)) _ ")" { Box::new(RequiredClasses(c)) }
+          / "forbids" _ "(" _ s:string() _ ")" { Box::new(ForbiddenSubstring(s)) }
+
+        rule expression() -> Box<dyn Policy> = precedence!{
+            x:(@) _ "or" _ y:@ { Box::new(Or(x, y)) }
+            --
+            x:(@) _ "xor" _ y:@ { Box::new(Xor(x, y)) }
+            --
+            x:(@) _ "and" _ y:@ { Box::new(And(x, y)) }
+            --
+            "not" _ x:@ { Box::new(Not(x)) }
+            --
+            a:atom() { a }
+            "(" _ e:expression() _ ")" { e }
+        }
+
+        pub rule policy() -> Box<dyn Policy> = _ p:expression() _ { p }
+    }
+}
+
+/// Parses a policy such as
+/// `min_length(8) and requires(digit, upper) and not forbids("password")`.
+///
+/// Atoms are `count('a', 1, 3)`, `position('a', 1, 3)`, `min_length(n)`,
+/// `requires(lower, upper, digit, symbol)` and `forbids("text")`, combined with
+/// `not`, `and`, `xor` and `or` from the tightest binding.
+pub fn parse_policy(s: &str) -> Result<Box<dyn Policy>> {
+    Ok(policy_parser::policy(s)?)
+}
+
 struct PasswordEntry {
     password: String,
     policy_char: char,
@@ -41,15 +341,35 @@ struct PasswordEntry {
     policy_upper_bound: usize,
 }
 
+impl PasswordEntry {
+    fn count_policy(&self) -> CountInRange {
+        CountInRange {
+            c: self.policy_char,
+            lo: self.policy_lower_bound,
+            hi: self.policy_upper_bound,
+        }
+    }
+
+    fn position_policy(&self) -> ExactlyOnePosition {
+        ExactlyOnePosition {
+            c: self.policy_char,
+            first: self.policy_lower_bound,
+            second: self.policy_upper_bound,
+        }
+    }
+}
+
 impl FromStr for PasswordEntry {
     type Err = Box<dyn Error>;
 
     fn from_str(s: &str) -> Result<Self> {
         lazy_static! {
             static ref RE: Regex =
-                Regex::new(r"(?P<lb>\d+)-(?P<ub>\d+) (?P<c>\w): (?P<pwd>\w+)").unwrap();
+                Regex::new(r"^(?P<lb>\d+)-(?P<ub>\d+) (?P<c>\w): (?P<pwd>\w+)$").unwrap();
         }
-        let cap = RE.captures(s).unwrap();
+        let cap = RE
+            .captures(s)
+            .ok_or_else(|| format!("expected '<lb>-<ub> <char>: <password>', got '{}'", s))?;
         Ok(PasswordEntry {
             password: cap.name("pwd").unwrap().as_str().parse()?,
             policy_char: cap.name("c").unwrap().as_str().parse()?,
@@ -59,9 +379,90 @@ impl FromStr for PasswordEntry {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyResult {
+    pub policy: String,
+    pub valid: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryReport {
+    pub line: usize,
+    pub password: String,
+    pub results: Vec<PolicyResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineError {
+    pub line: usize,
+    pub content: String,
+    pub error: String,
+}
+
+/// Every entry checked against its own count and position policies and the
+/// `extra` ones, lines which are not entries are listed as errors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    pub entries: Vec<EntryReport>,
+    pub errors: Vec<LineError>,
+}
+
+impl ValidationReport {
+    pub fn new(input: &str, extra: &[Box<dyn Policy>]) -> Self {
+        let mut report = ValidationReport {
+            entries: vec![],
+            errors: vec![],
+        };
+
+        let lines = input.lines().map(|l| l.trim()).enumerate();
+        for (i, l) in lines.filter(|(_, l)| !l.is_empty()) {
+            let entry = match l.parse::<PasswordEntry>() {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report.errors.push(LineError {
+                        line: i + 1,
+                        content: l.to_owned(),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let count_policy = entry.count_policy();
+            let position_policy = entry.position_policy();
+            let policies = vec![&count_policy as &dyn Policy, &position_policy]
+                .into_iter()
+                .chain(extra.iter().map(|p| p.as_ref()));
+            report.entries.push(EntryReport {
+                line: i + 1,
+                password: entry.password.to_owned(),
+                results: policies
+                    .map(|p| {
+                        let reason = p.violation(&entry.password);
+                        PolicyResult {
+                            policy: p.to_string(),
+                            valid: reason.is_none(),
+                            reason,
+                        }
+                    })
+                    .collect(),
+            });
+        }
+
+        report
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2};
+    use super::{
+        count_valid, input_transformer, parse_policy, solve_part1, solve_part2, ValidationReport,
+    };
     use crate::utils::*;
 
     const DAY: u32 = 2;
@@ -71,7 +472,7 @@ mod tests {
 
     #[test]
     fn test_part1_sample() {
-        let solution = solve_part1(input_transformer(SAMPLE));
+        let solution = solve_part1(input_transformer(SAMPLE).unwrap());
 
         assert_eq!(2, solution);
     }
@@ -79,14 +480,14 @@ mod tests {
     #[test]
     fn test_part1_puzzle() {
         let input = read_input(2020, DAY).unwrap();
-        let solution = solve_part1(input_transformer(&input));
+        let solution = solve_part1(input_transformer(&input).unwrap());
 
         assert_eq!(439, solution);
     }
 
     #[test]
     fn test_part2_sample() {
-        let solution = solve_part2(input_transformer(SAMPLE));
+        let solution = solve_part2(input_transformer(SAMPLE).unwrap());
 
         assert_eq!(1, solution);
     }
@@ -94,8 +495,81 @@ mod tests {
     #[test]
     fn test_part2_puzzle() {
         let input = read_input(2020, DAY).unwrap();
-        let solution = solve_part2(input_transformer(&input));
+        let solution = solve_part2(input_transformer(&input).unwrap());
 
         assert_eq!(584, solution);
     }
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = parse_policy("count('a', 1, 3) xor position('a', 1, 3)").unwrap();
+        assert_eq!(1, count_valid(vec!["abcde", "aba", "bbb"], policy.as_ref()));
+
+        assert!(parse_policy("min_length(8) and").is_err());
+        assert!(parse_policy("requires(digits)").is_err());
+
+        let policy =
+            parse_policy("min_length(8) and requires(digit, upper) and forbids(\"word\")").unwrap();
+        assert!(policy.check("Secret123"));
+        assert!(!policy.check("Password123"));
+        assert!(!policy.check("secret123"));
+        assert!(!policy.check("Secret1"));
+    }
+
+    #[test]
+    fn test_policy_precedence() {
+        // `and` binds tighter than `xor` which binds tighter than `or`
+        let policy = parse_policy("requires(digit) or requires(upper) and min_length(3)").unwrap();
+        assert!(policy.check("1"));
+        assert!(!policy.check("A"));
+
+        let policy =
+            parse_policy("not requires(digit) xor (min_length(2) or requires(symbol))").unwrap();
+        assert!(policy.check("a"));
+        assert!(!policy.check("ab"));
+        assert!(policy.check("a1"));
+        assert!(!policy.check("1"));
+    }
+
+    #[test]
+    fn test_validation_report() {
+        let input = "1-3 a: abcde
+            1-3 b: cdefg
+            oops
+            2-9 c: ccccccccc";
+        let extra = vec![parse_policy("min_length(6) or requires(digit)").unwrap()];
+        let report = ValidationReport::new(input, &extra);
+
+        assert_eq!(3, report.entries.len());
+        assert_eq!(4, report.entries[2].line);
+        let results = &report.entries[1].results;
+        assert_eq!("count('b', 1, 3)", results[0].policy);
+        assert_eq!(
+            Some("char 'b' appears 0 times, allowed 1-3".to_string()),
+            results[0].reason
+        );
+        assert_eq!(
+            Some("char 'b' is at neither position 1 nor 3".to_string()),
+            results[1].reason
+        );
+        assert_eq!("(min_length(6) or requires(digit))", results[2].policy);
+        assert_eq!(
+            Some("length is 5, required at least 6; missing digit".to_string()),
+            results[2].reason
+        );
+        assert!(report.entries[2].results[0].valid);
+
+        assert_eq!(1, report.errors.len());
+        assert_eq!(3, report.errors[0].line);
+        assert_eq!("oops", report.errors[0].content);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(false, json["entries"][1]["results"][0]["valid"]);
+        assert_eq!(3, json["errors"][0]["line"]);
+    }
+
+    #[test]
+    fn test_malformed_entry() {
+        assert!(input_transformer("1-3 a: abcde\n1-3 a abcde").is_err());
+    }
 }
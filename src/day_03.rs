@@ -12,7 +12,7 @@ fn solve_part1(input: Input) -> Output {
 fn solve_part2(input: Input) -> Output {
     let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
 
-    slopes.iter().map(|s| solve_slope(&input, &s)).product()
+    slopes.iter().map(|s| solve_slope(&input, s)).product()
 }
 
 type Slope = (usize, usize);
@@ -32,9 +32,84 @@ fn solve_slope(input: &[Vec<char>], (dc, dr): &Slope) -> Output {
     count
 }
 
+/// Trees hit going down one slope, as `(row, column)` in the order they are hit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlopeTrees {
+    pub slope: Slope,
+    pub trees: Vec<(usize, usize)>,
+}
+
+impl SlopeTrees {
+    pub fn count(&self) -> usize {
+        self.trees.len()
+    }
+}
+
+/// Trees hit for every slope with `1 <= dr <= max_dr` and `0 <= dc <= max_dc`,
+/// walking each row of the map once for all slopes. Empty rows are skipped.
+pub fn survey(input: &[Vec<char>], max_dc: usize, max_dr: usize) -> Vec<SlopeTrees> {
+    let mut results: Vec<SlopeTrees> = (1..=max_dr)
+        .flat_map(|dr| (0..=max_dc).map(move |dc| (dc, dr)))
+        .map(|slope| SlopeTrees {
+            slope,
+            trees: vec![],
+        })
+        .collect();
+
+    for (r, row) in input.iter().enumerate().filter(|(_, row)| !row.is_empty()) {
+        for result in results.iter_mut() {
+            let (dc, dr) = result.slope;
+            if r % dr != 0 {
+                continue;
+            }
+            let c = (r / dr * dc) % row.len();
+            if row[c] == '#' {
+                result.trees.push((r, c));
+            }
+        }
+    }
+
+    results
+}
+
+pub fn fewest_trees(input: &[Vec<char>], max_dc: usize, max_dr: usize) -> Option<SlopeTrees> {
+    survey(input, max_dc, max_dr)
+        .into_iter()
+        .min_by_key(|s| s.count())
+}
+
+pub fn most_trees(input: &[Vec<char>], max_dc: usize, max_dr: usize) -> Option<SlopeTrees> {
+    survey(input, max_dc, max_dr)
+        .into_iter()
+        .max_by_key(|s| s.count())
+}
+
+/// The map with the path of `slope` drawn on it like the puzzle does, `X`
+/// for a tree hit and `O` for an open square passed through. `None` for a
+/// slope that never moves down.
+pub fn overlay(input: &[Vec<char>], &(dc, dr): &Slope) -> Option<String> {
+    if dr == 0 {
+        return None;
+    }
+
+    let mut lines = vec![];
+    for (r, row) in input.iter().enumerate() {
+        let mut line = row.clone();
+        if r % dr == 0 && !row.is_empty() {
+            let c = (r / dr * dc) % row.len();
+            line[c] = if row[c] == '#' { 'X' } else { 'O' };
+        }
+        lines.push(line.into_iter().collect::<String>());
+    }
+    Some(lines.join("\n"))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2};
+    use super::{
+        fewest_trees, input_transformer, most_trees, overlay, solve_part1, solve_part2,
+        solve_slope, survey,
+    };
     use crate::utils::*;
 
     const SAMPLE: &str = "..##.......
@@ -78,4 +153,53 @@ mod tests {
 
         assert_eq!(3898725600, solution);
     }
+
+    #[test]
+    fn test_survey() {
+        let input = input_transformer(SAMPLE);
+        let results = survey(&input, 7, 2);
+
+        assert_eq!(16, results.len());
+        for result in &results {
+            assert_eq!(solve_slope(&input, &result.slope), result.count() as u64);
+        }
+        let (rows, _): (Vec<usize>, Vec<usize>) = results[3].trees.iter().cloned().unzip();
+        assert_eq!((3, 1), results[3].slope);
+        assert_eq!(vec![2, 4, 5, 7, 8, 9, 10], rows);
+    }
+
+    #[test]
+    fn test_fewest_and_most_trees() {
+        let input = input_transformer(SAMPLE);
+
+        let fewest = fewest_trees(&input, 7, 2).unwrap();
+        let most = most_trees(&input, 7, 2).unwrap();
+        assert!(survey(&input, 7, 2)
+            .iter()
+            .all(|s| fewest.count() <= s.count() && s.count() <= most.count()));
+        assert_eq!(None, fewest_trees(&input, 7, 0));
+    }
+
+    #[test]
+    fn test_overlay() {
+        let input = input_transformer(SAMPLE);
+        let map = overlay(&input, &(3, 1)).unwrap();
+        let lines: Vec<&str> = map.lines().collect();
+
+        assert_eq!("O.##.......", lines[0]);
+        assert_eq!("#..O#...#..", lines[1]);
+        assert_eq!(".#....X..#.", lines[2]);
+        assert_eq!(7, map.matches('X').count());
+    }
+
+    #[test]
+    fn test_degenerate_slopes_and_rows() {
+        let mut input = input_transformer(SAMPLE);
+        input.push(vec![]);
+
+        assert_eq!(None, overlay(&input, &(1, 0)));
+        assert!(overlay(&input, &(3, 1)).unwrap().ends_with(".#..#...X.#\n"));
+        assert_eq!(7, survey(&input, 3, 1)[3].count());
+        assert!(survey(&input, 3, 0).is_empty());
+    }
 }
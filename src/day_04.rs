@@ -1,132 +1,319 @@
 use std::collections::*;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
 
 use regex::Regex;
+use serde::Deserialize;
+
+use crate::utils::*;
 
 const DAY: u32 = 4;
 
 type Input = Vec<BTreeMap<String, String>>;
 type Output = usize;
 
-lazy_static! {
-    static ref KV_RE: Regex = Regex::new(r"\b(?P<key>\w{3}):(?P<value>#?\w+)\b").unwrap();
+/// Rules for a single passport field, every rule given must hold.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FieldRule {
+    pub required: bool,
+    pub pattern: Option<String>,
+    /// Inclusive bounds of the numeric value.
+    pub range: Option<(i64, i64)>,
+    /// Inclusive bounds of the numeric prefix, by unit suffix.
+    pub units: BTreeMap<String, (i64, i64)>,
+    pub one_of: Vec<String>,
 }
 
-lazy_static! {
-    static ref VALUE_RE: HashMap<String, Regex> = hashmap! {
-        "byr".to_string() => Regex::new(r"^(\d{4})$").unwrap(),
-        "iyr".to_string() => Regex::new(r"^(\d{4})$").unwrap(),
-        "eyr".to_string() => Regex::new(r"^(\d{4})$").unwrap(),
-        "hgt".to_string() => Regex::new(r"^(\d+)(in|cm)$").unwrap(),
-        "hcl".to_string() => Regex::new(r"^(#[a-f0-9]{6})$").unwrap(),
-        "ecl".to_string() => Regex::new(r"^(amb|blu|brn|gry|grn|hzl|oth)$").unwrap(),
-        "pid".to_string() => Regex::new(r"^(\d{9})$").unwrap(),
-    };
+#[derive(Debug, Clone)]
+pub struct Schema {
+    fields: BTreeMap<String, (FieldRule, Option<Regex>)>,
 }
 
-fn group_rows(input: &str) -> Vec<String> {
-    let mut raw_passports: Vec<String> = vec![];
-    let mut buffer = String::new();
-    for l in input.lines().map(|l| l.trim()) {
-        if l.is_empty() {
-            raw_passports.push(buffer.trim().to_string());
-            buffer.clear();
-        } else {
-            buffer.push_str(&format!(" {}", l));
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldError {
+    Missing(String),
+    Pattern {
+        field: String,
+        value: String,
+    },
+    NotANumber {
+        field: String,
+        value: String,
+    },
+    OutOfRange {
+        field: String,
+        value: i64,
+        lo: i64,
+        hi: i64,
+    },
+    UnknownUnit {
+        field: String,
+        value: String,
+    },
+    NotOneOf {
+        field: String,
+        value: String,
+    },
+}
+
+impl FieldError {
+    pub fn field(&self) -> &str {
+        match self {
+            FieldError::Missing(field)
+            | FieldError::Pattern { field, .. }
+            | FieldError::NotANumber { field, .. }
+            | FieldError::OutOfRange { field, .. }
+            | FieldError::UnknownUnit { field, .. }
+            | FieldError::NotOneOf { field, .. } => field,
         }
     }
-    if !buffer.is_empty() {
-        raw_passports.push(buffer.trim().to_string());
-        buffer.clear();
-    }
+}
 
-    raw_passports
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Missing(field) => write!(f, "{}: required field is missing", field),
+            FieldError::Pattern { field, value } => {
+                write!(f, "{}: '{}' does not match the pattern", field, value)
+            }
+            FieldError::NotANumber { field, value } => {
+                write!(f, "{}: '{}' is not a number", field, value)
+            }
+            FieldError::OutOfRange {
+                field,
+                value,
+                lo,
+                hi,
+            } => write!(f, "{}: {} is not within {}-{}", field, value, lo, hi),
+            FieldError::UnknownUnit { field, value } => {
+                write!(f, "{}: '{}' has no known unit", field, value)
+            }
+            FieldError::NotOneOf { field, value } => {
+                write!(f, "{}: '{}' is not an allowed value", field, value)
+            }
+        }
+    }
 }
 
-fn extract_details(raw_passports: &[String]) -> Input {
-    let mut passports: Input = vec![];
-    for p in raw_passports {
-        let passport = KV_RE
-            .captures_iter(&p)
-            .map(|c| {
-                (
-                    c.name("key").unwrap().as_str().to_string(),
-                    c.name("value").unwrap().as_str().to_string(),
-                )
+impl Error for FieldError {}
+
+impl Schema {
+    pub fn new(rules: BTreeMap<String, FieldRule>) -> Result<Self> {
+        let mut fields = BTreeMap::new();
+        for (name, rule) in rules {
+            let pattern = match &rule.pattern {
+                Some(p) => Some(Regex::new(p)?),
+                None => None,
+            };
+            fields.insert(name, (rule, pattern));
+        }
+
+        Ok(Schema { fields })
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        Self::new(toml::from_str(s)?)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self> {
+        Self::new(serde_json::from_str(s)?)
+    }
+
+    /// Loads a `.json` schema, anything else is read as TOML.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&s),
+            _ => Self::from_toml(&s),
+        }
+    }
+
+    pub fn rule(&self, field: &str) -> Option<&FieldRule> {
+        self.fields.get(field).map(|(rule, _)| rule)
+    }
+
+    /// The first broken rule of every field, fields the schema does not
+    /// describe are ignored.
+    pub fn validate(&self, passport: &BTreeMap<String, String>) -> Vec<FieldError> {
+        self.fields
+            .iter()
+            .filter_map(|(name, (rule, pattern))| match passport.get(name) {
+                None if rule.required => Some(FieldError::Missing(name.to_owned())),
+                None => None,
+                Some(value) => check_field(name, value, rule, pattern.as_ref()).err(),
             })
-            .filter(|(k, _)| VALUE_RE.contains_key(k))
-            .collect();
+            .collect()
+    }
 
-        passports.push(passport);
+    pub fn is_complete(&self, passport: &BTreeMap<String, String>) -> bool {
+        self.validate(passport)
+            .iter()
+            .all(|e| !matches!(e, FieldError::Missing(_)))
     }
 
-    passports
+    pub fn is_valid(&self, passport: &BTreeMap<String, String>) -> bool {
+        self.validate(passport).is_empty()
+    }
 }
 
-fn input_transformer(input: &str) -> Input {
-    let raw_passports = group_rows(input);
-    extract_details(&raw_passports)
+/// The puzzle's passport rules.
+impl Default for Schema {
+    fn default() -> Self {
+        Self::from_toml(include_str!("day_04/passport.toml")).unwrap()
+    }
 }
 
-fn solve_part1(input: Input) -> Output {
-    let required: HashSet<_> = VALUE_RE.keys().into_iter().collect();
-    input
-        .iter()
-        .filter(|p| {
-            let found: HashSet<&String> = p.keys().into_iter().collect();
-
-            required.difference(&found).count() == 0
+fn check_field(
+    name: &str,
+    value: &str,
+    rule: &FieldRule,
+    pattern: Option<&Regex>,
+) -> std::result::Result<(), FieldError> {
+    let error = |make: fn(String, String) -> FieldError| make(name.to_owned(), value.to_owned());
+    let number = |n: &str| {
+        n.parse::<i64>().map_err(|_| FieldError::NotANumber {
+            field: name.to_owned(),
+            value: value.to_owned(),
         })
-        .count()
+    };
+    let between = |n: i64, (lo, hi): (i64, i64)| {
+        if lo <= n && n <= hi {
+            Ok(())
+        } else {
+            Err(FieldError::OutOfRange {
+                field: name.to_owned(),
+                value: n,
+                lo,
+                hi,
+            })
+        }
+    };
+
+    if pattern.is_some_and(|p| !p.is_match(value)) {
+        return Err(error(|field, value| FieldError::Pattern { field, value }));
+    }
+    if !rule.one_of.is_empty() && !rule.one_of.iter().any(|v| v == value) {
+        return Err(error(|field, value| FieldError::NotOneOf { field, value }));
+    }
+    if let Some(range) = rule.range {
+        between(number(value)?, range)?;
+    }
+    if !rule.units.is_empty() {
+        // The longest unit wins, so `min` is not read as `m` + `in`
+        let (n, range) = rule
+            .units
+            .iter()
+            .filter_map(|(unit, range)| value.strip_suffix(unit.as_str()).map(|n| (n, *range)))
+            .min_by_key(|(n, _)| n.len())
+            .ok_or_else(|| error(|field, value| FieldError::UnknownUnit { field, value }))?;
+        between(number(n)?, range)?;
+    }
+
+    Ok(())
 }
 
-fn solve_part2(input: Input) -> Output {
-    let required: HashSet<_> = VALUE_RE.keys().into_iter().collect();
+/// A `key:value` pair as written, with the line it is on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
 
-    input
-        .iter()
-        .filter(|p| {
-            let found: HashSet<&String> = p.keys().into_iter().collect();
+/// Every field of one record in input order, including repeated keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Line the record starts on.
+    pub line: usize,
+    pub fields: Vec<Field>,
+    /// Tokens without a `:`, with the line they are on.
+    pub malformed: Vec<(usize, String)>,
+}
 
-            required.difference(&found).count() == 0
-        })
-        .filter(|p| {
-            let count = p
-                .iter()
-                .filter(|(k, _)| VALUE_RE.contains_key(*k))
-                .filter(|(k, v)| {
-                    let ok = VALUE_RE.get(*k).unwrap().is_match(v);
-                    ok && match &k[..] {
-                        "byr" => str_num_between(v, 1920, 2002),
-                        "iyr" => str_num_between(v, 2010, 2020),
-                        "eyr" => str_num_between(v, 2020, 2030),
-                        "hgt" => {
-                            let n = &v[..v.len() - 2];
-                            let units = &v[v.len() - 2..];
-                            match units {
-                                "in" => str_num_between(n, 59, 76),
-                                "cm" => str_num_between(n, 150, 193),
-                                _ => false,
-                            }
-                        }
-                        _ => true,
-                    }
-                })
-                .count();
-
-            VALUE_RE.keys().len() == count
+impl Record {
+    pub fn get(&self, key: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.key == key)
+    }
+
+    /// Fields whose key already appeared earlier in the record.
+    pub fn duplicates(&self) -> Vec<&Field> {
+        let mut seen = HashSet::new();
+        self.fields
+            .iter()
+            .filter(|f| !seen.insert(&f.key))
+            .collect()
+    }
+
+    /// Fields the schema does not describe.
+    pub fn unknown(&self, schema: &Schema) -> Vec<&Field> {
+        self.fields
+            .iter()
+            .filter(|f| schema.rule(&f.key).is_none())
+            .collect()
+    }
+
+    /// Value of every key, the first one where a key is repeated.
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        let mut map = BTreeMap::new();
+        for f in &self.fields {
+            map.entry(f.key.to_owned())
+                .or_insert_with(|| f.value.to_owned());
+        }
+        map
+    }
+}
+
+pub fn parse_records(input: &str) -> Vec<Record> {
+    paragraphs(input)
+        .map(|p| {
+            let mut record = Record {
+                line: p.line,
+                fields: vec![],
+                malformed: vec![],
+            };
+            for (line, token) in p
+                .lines()
+                .flat_map(|(i, l)| l.split_whitespace().map(move |t| (i, t)))
+            {
+                match token.split_once(':') {
+                    Some((key, value)) => record.fields.push(Field {
+                        key: key.to_owned(),
+                        value: value.to_owned(),
+                        line,
+                    }),
+                    None => record.malformed.push((line, token.to_owned())),
+                }
+            }
+            record
         })
-        .count()
+        .collect()
+}
+
+fn input_transformer(input: &str) -> Input {
+    parse_records(input).iter().map(|r| r.to_map()).collect()
 }
 
-fn str_num_between(s: &str, l: i32, u: i32) -> bool {
-    let n = s.parse::<i32>().unwrap();
-    l <= n && n <= u
+fn solve_part1(input: Input) -> Output {
+    let schema = Schema::default();
+    input.iter().filter(|p| schema.is_complete(p)).count()
+}
+
+fn solve_part2(input: Input) -> Output {
+    let schema = Schema::default();
+    input.iter().filter(|p| schema.is_valid(p)).count()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{
+        input_transformer, parse_records, solve_part1, solve_part2, FieldError, Schema, DAY,
+    };
     use crate::utils::*;
+    use std::collections::BTreeMap;
 
     const SAMPLE: &str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
         byr:1937 iyr:2017 cid:147 hgt:183cm
@@ -217,4 +404,136 @@ mod tests {
 
         assert_eq!(114, solution);
     }
+
+    #[test]
+    fn test_field_errors() {
+        let passport = input_transformer(
+            "byr:2003 iyr:2015 eyr:2025x hgt:190in
+            hcl:#123abz ecl:wat cid:1",
+        )
+        .remove(0);
+        let errors = Schema::default().validate(&passport);
+
+        let expected = vec![
+            FieldError::OutOfRange {
+                field: "byr".to_string(),
+                value: 2003,
+                lo: 1920,
+                hi: 2002,
+            },
+            FieldError::NotOneOf {
+                field: "ecl".to_string(),
+                value: "wat".to_string(),
+            },
+            FieldError::Pattern {
+                field: "eyr".to_string(),
+                value: "2025x".to_string(),
+            },
+            FieldError::Pattern {
+                field: "hcl".to_string(),
+                value: "#123abz".to_string(),
+            },
+            FieldError::OutOfRange {
+                field: "hgt".to_string(),
+                value: 190,
+                lo: 59,
+                hi: 76,
+            },
+            FieldError::Missing("pid".to_string()),
+        ];
+        assert_eq!(expected, errors);
+        assert_eq!("byr: 2003 is not within 1920-2002", errors[0].to_string());
+    }
+
+    #[test]
+    fn test_schema_from_json() {
+        let schema = Schema::from_json(
+            r#"{
+                "len": { "required": true, "units": { "m": [1, 10], "km": [0, 2] } },
+                "tag": { "pattern": "^[a-z]+$" }
+            }"#,
+        )
+        .unwrap();
+        let passport = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        assert!(schema.is_valid(&passport(&[("len", "2km")])));
+        assert!(schema.is_valid(&passport(&[("len", "10m"), ("tag", "abc")])));
+        assert_eq!(
+            vec!["len", "tag"],
+            schema
+                .validate(&passport(&[("len", "3ft"), ("tag", "A")]))
+                .iter()
+                .map(|e| e.field())
+                .collect::<Vec<_>>()
+        );
+        assert!(!schema.is_complete(&passport(&[("tag", "abc")])));
+        assert!(Schema::from_json(r#"{ "x": { "pattern": "(" } }"#).is_err());
+        assert!(Schema::from_toml("[x]\nrequried = true").is_err());
+    }
+
+    #[test]
+    fn test_longest_unit_wins() {
+        let schema = Schema::from_toml("[t]\nunits = { in = [1, 5], min = [10, 60] }").unwrap();
+        let passport = |value: &str| -> BTreeMap<String, String> {
+            vec![("t".to_string(), value.to_string())]
+                .into_iter()
+                .collect()
+        };
+
+        assert!(schema.is_valid(&passport("15min")));
+        assert!(schema.is_valid(&passport("5in")));
+        assert_eq!(
+            vec![FieldError::OutOfRange {
+                field: "t".to_string(),
+                value: 5,
+                lo: 10,
+                hi: 60
+            }],
+            schema.validate(&passport("5min"))
+        );
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let input = "ecl:gry pid:1 ecl:blu\r\n  zzz:a:b  \r\n\r\n \t\r\nhcl:#fff   bad\n\nhgt:";
+        let records = parse_records(input);
+
+        assert_eq!(3, records.len());
+        assert_eq!(
+            vec![1, 5, 7],
+            records.iter().map(|r| r.line).collect::<Vec<_>>()
+        );
+
+        let first = &records[0];
+        assert_eq!(4, first.fields.len());
+        assert_eq!(("zzz", "a:b", 2), {
+            let f = &first.fields[3];
+            (f.key.as_str(), f.value.as_str(), f.line)
+        });
+        assert_eq!(
+            vec![("ecl", "blu")],
+            first
+                .duplicates()
+                .iter()
+                .map(|f| (f.key.as_str(), f.value.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&"gry".to_string()), first.to_map().get("ecl"));
+        assert_eq!(
+            vec!["zzz"],
+            first
+                .unknown(&Schema::default())
+                .iter()
+                .map(|f| f.key.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(vec![(5, "bad".to_string())], records[1].malformed);
+        assert_eq!("", records[2].get("hgt").unwrap().value);
+    }
 }
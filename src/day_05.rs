@@ -1,36 +1,256 @@
 use crate::utils::*;
 use itertools::Itertools;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
 use std::str::FromStr;
 
 const DAY: u32 = 5;
 
-struct BordingPass(String);
+/// Plane size as the number of bits encoding a row and a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Geometry {
+    pub row_bits: u32,
+    pub column_bits: u32,
+}
+
+impl Geometry {
+    pub fn rows(&self) -> usize {
+        1 << self.row_bits
+    }
+
+    pub fn columns(&self) -> usize {
+        1 << self.column_bits
+    }
+
+    pub fn code_len(&self) -> usize {
+        (self.row_bits + self.column_bits) as usize
+    }
+
+    pub fn parse(self, s: &str) -> std::result::Result<BoardingPass, BoardingPassError> {
+        let code: Vec<char> = s.chars().collect();
+        if code.len() != self.code_len() {
+            return Err(BoardingPassError::Length {
+                expected: self.code_len(),
+                found: code.len(),
+            });
+        }
+
+        let (row_code, column_code) = code.split_at(self.row_bits as usize);
+        let decode = |code: &[char], offset: usize, (zero, one): (char, char)| {
+            code.iter().enumerate().try_fold(0, |n, (i, &c)| match c {
+                c if c == zero => Ok(n << 1),
+                c if c == one => Ok(n << 1 | 1),
+                c => Err(BoardingPassError::InvalidChar {
+                    position: offset + i,
+                    c,
+                }),
+            })
+        };
+
+        Ok(BoardingPass {
+            row: decode(row_code, 0, ('F', 'B'))?,
+            column: decode(column_code, row_code.len(), ('L', 'R'))?,
+            geometry: self,
+        })
+    }
+}
+
+/// The puzzle's 128 rows of 8 seats.
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry {
+            row_bits: 7,
+            column_bits: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoardingPassError {
+    Length { expected: usize, found: usize },
+    InvalidChar { position: usize, c: char },
+    OutOfRange { row: usize, column: usize },
+}
+
+impl fmt::Display for BoardingPassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardingPassError::Length { expected, found } => {
+                write!(f, "expected a code of {} letters, got {}", expected, found)
+            }
+            BoardingPassError::InvalidChar { position, c } => {
+                write!(f, "unexpected '{}' at position {}", c, position)
+            }
+            BoardingPassError::OutOfRange { row, column } => {
+                write!(f, "no seat at row {}, column {}", row, column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BoardingPassError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoardingPass {
+    row: usize,
+    column: usize,
+    geometry: Geometry,
+}
+
+impl BoardingPass {
+    pub fn new(
+        row: usize,
+        column: usize,
+        geometry: Geometry,
+    ) -> std::result::Result<Self, BoardingPassError> {
+        if row >= geometry.rows() || column >= geometry.columns() {
+            return Err(BoardingPassError::OutOfRange { row, column });
+        }
+
+        Ok(BoardingPass {
+            row,
+            column,
+            geometry,
+        })
+    }
 
-impl BordingPass {
-    fn row(&self) -> usize {
-        let binary = &self.0[..7].replace("F", "0").replace("B", "1");
-        usize::from_str_radix(binary, 2).unwrap()
+    pub fn from_seat_id(
+        id: usize,
+        geometry: Geometry,
+    ) -> std::result::Result<Self, BoardingPassError> {
+        let column = id & (geometry.columns() - 1);
+        Self::new(id >> geometry.column_bits, column, geometry)
     }
 
-    fn column(&self) -> usize {
-        let binary = &self.0[7..].replace("L", "0").replace("R", "1");
-        usize::from_str_radix(binary, 2).unwrap()
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
     }
 
     pub fn seat_id(&self) -> usize {
-        self.row() * 8 + self.column()
+        self.row << self.geometry.column_bits | self.column
+    }
+
+    pub fn code(&self) -> String {
+        let encode = |n: usize, bits: u32, (zero, one): (char, char)| {
+            (0..bits)
+                .rev()
+                .map(move |b| if n >> b & 1 == 1 { one } else { zero })
+        };
+
+        encode(self.row, self.geometry.row_bits, ('F', 'B'))
+            .chain(encode(self.column, self.geometry.column_bits, ('L', 'R')))
+            .collect()
     }
 }
 
-impl FromStr for BordingPass {
-    type Err = Error;
+impl FromStr for BoardingPass {
+    type Err = BoardingPassError;
 
-    fn from_str(s: &str) -> Result<Self> {
-        Ok(BordingPass(s.to_string()))
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Geometry::default().parse(s)
     }
 }
 
-type Input = Vec<BordingPass>;
+impl fmt::Display for BoardingPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// One line per row, `#` for a seat on one of the passes and `.` for an
+/// empty one.
+pub fn seat_map(passes: &[BoardingPass], geometry: Geometry) -> String {
+    let taken: HashSet<(usize, usize)> = passes.iter().map(|p| (p.row, p.column)).collect();
+
+    (0..geometry.rows())
+        .map(|r| {
+            (0..geometry.columns())
+                .map(|c| if taken.contains(&(r, c)) { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .join("\n")
+}
+
+/// The seats taken by a set of passes. Queries only return free seats in the
+/// rows from the first to the last row with a taken seat, as the empty rows at
+/// the very front and back of the plane have no real seats.
+#[derive(Debug, Clone)]
+pub struct SeatPlan {
+    geometry: Geometry,
+    taken: BTreeSet<usize>,
+}
+
+impl SeatPlan {
+    pub fn new(passes: &[BoardingPass], geometry: Geometry) -> Self {
+        SeatPlan {
+            geometry,
+            taken: passes.iter().map(|p| p.seat_id()).collect(),
+        }
+    }
+
+    pub fn is_taken(&self, id: usize) -> bool {
+        self.taken.contains(&id)
+    }
+
+    fn seat(&self, id: usize) -> BoardingPass {
+        BoardingPass::from_seat_id(id, self.geometry).unwrap()
+    }
+
+    /// Free seats in seat id order.
+    pub fn free_seats(&self) -> Vec<BoardingPass> {
+        let (first, last) = match (self.taken.iter().next(), self.taken.iter().next_back()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return vec![],
+        };
+        let row_start = |id: usize| id >> self.geometry.column_bits << self.geometry.column_bits;
+
+        (row_start(first)..row_start(last) + self.geometry.columns())
+            .filter(|&id| !self.is_taken(id))
+            .map(|id| self.seat(id))
+            .collect()
+    }
+
+    /// Free seats whose ids either side are both taken.
+    pub fn free_between_taken(&self) -> Vec<BoardingPass> {
+        self.free_seats()
+            .into_iter()
+            .filter(|p| {
+                let id = p.seat_id();
+                id.checked_sub(1).is_some_and(|id| self.is_taken(id)) && self.is_taken(id + 1)
+            })
+            .collect()
+    }
+
+    pub fn free_in_rows(&self, rows: &[usize]) -> Vec<BoardingPass> {
+        self.free_seats()
+            .into_iter()
+            .filter(|p| rows.contains(&p.row()))
+            .collect()
+    }
+
+    /// Every run of `n` free seats next to each other in the same row.
+    pub fn free_blocks(&self, n: usize) -> Vec<Vec<BoardingPass>> {
+        if n == 0 {
+            return vec![];
+        }
+
+        self.free_seats()
+            .windows(n)
+            .filter(|w| {
+                w.iter()
+                    .tuple_windows()
+                    .all(|(a, b)| a.row() == b.row() && a.column() + 1 == b.column())
+            })
+            .map(|w| w.to_vec())
+            .collect()
+    }
+}
+
+type Input = Vec<BoardingPass>;
 type Output = Option<usize>;
 
 fn input_transformer(input: &str) -> Input {
@@ -42,22 +262,18 @@ fn solve_part1(input: Input) -> Output {
 }
 
 fn solve_part2(input: Input) -> Output {
-    let seats: Vec<usize> = input.iter().map(|l| l.seat_id()).sorted().collect();
-
-    for i in 1..seats.len() {
-        let prev = seats[i - 1];
-        let curr = seats[i];
-        if prev + 2 == curr {
-            return Some(prev + 1);
-        }
-    }
-
-    None
+    SeatPlan::new(&input, Geometry::default())
+        .free_between_taken()
+        .first()
+        .map(|p| p.seat_id())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, BordingPass, DAY};
+    use super::{
+        input_transformer, seat_map, solve_part1, solve_part2, BoardingPass, BoardingPassError,
+        Geometry, SeatPlan, DAY,
+    };
     use crate::utils::*;
 
     const SAMPLE: &str = "BFFFBBFRRR
@@ -65,10 +281,10 @@ mod tests {
         BBFFBBFRLL";
 
     #[test]
-    fn test_bordingpass() {
-        let bp1: BordingPass = "BFFFBBFRRR".parse().unwrap();
-        let bp2: BordingPass = "FFFBBBFRRR".parse().unwrap();
-        let bp3: BordingPass = "BBFFBBFRLL".parse().unwrap();
+    fn test_boarding_pass() {
+        let bp1: BoardingPass = "BFFFBBFRRR".parse().unwrap();
+        let bp2: BoardingPass = "FFFBBBFRRR".parse().unwrap();
+        let bp3: BoardingPass = "BBFFBBFRLL".parse().unwrap();
 
         assert_eq!(70, bp1.row());
         assert_eq!(7, bp1.column());
@@ -112,4 +328,78 @@ mod tests {
 
         assert_eq!(Some(646), solution);
     }
+
+    #[test]
+    fn test_boarding_pass_errors() {
+        assert_eq!(
+            Err(BoardingPassError::Length {
+                expected: 10,
+                found: 9
+            }),
+            "BFFFBBFRR".parse::<BoardingPass>()
+        );
+        assert_eq!(
+            Err(BoardingPassError::InvalidChar {
+                position: 7,
+                c: 'F'
+            }),
+            "BFFFBBFFRR".parse::<BoardingPass>()
+        );
+        assert_eq!(
+            Err(BoardingPassError::OutOfRange { row: 0, column: 8 }),
+            BoardingPass::new(0, 8, Geometry::default())
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        let pass = BoardingPass::new(70, 7, Geometry::default()).unwrap();
+        assert_eq!("BFFFBBFRRR", pass.code());
+        assert_eq!(
+            pass,
+            BoardingPass::from_seat_id(567, Geometry::default()).unwrap()
+        );
+
+        let small = Geometry {
+            row_bits: 2,
+            column_bits: 1,
+        };
+        let pass = small.parse("BFR").unwrap();
+        assert_eq!((2, 1, 5), (pass.row(), pass.column(), pass.seat_id()));
+        assert_eq!("BFR", pass.to_string());
+
+        let passes = vec![pass, small.parse("FFL").unwrap()];
+        assert_eq!("#.\n..\n.#\n..", seat_map(&passes, small));
+    }
+
+    #[test]
+    fn test_seat_plan() {
+        let small = Geometry {
+            row_bits: 2,
+            column_bits: 2,
+        };
+        // Row 0 is missing, row 1 has seats 4 and 6 free, row 2 seats 10 and 11
+        // and row 3 is full
+        let passes = [5, 7, 8, 9, 12, 13, 14, 15]
+            .iter()
+            .map(|&id| BoardingPass::from_seat_id(id, small).unwrap())
+            .collect::<Vec<_>>();
+        let plan = SeatPlan::new(&passes, small);
+        let ids = |seats: Vec<BoardingPass>| seats.iter().map(|p| p.seat_id()).collect::<Vec<_>>();
+
+        assert_eq!(vec![4, 6, 10, 11], ids(plan.free_seats()));
+        assert_eq!(vec![6], ids(plan.free_between_taken()));
+        assert_eq!("FBRL", plan.free_between_taken()[0].code());
+        assert_eq!(vec![10, 11], ids(plan.free_in_rows(&[0, 2])));
+        assert_eq!(
+            vec![vec![10, 11]],
+            plan.free_blocks(2).into_iter().map(ids).collect::<Vec<_>>()
+        );
+        assert!(plan.free_blocks(3).is_empty());
+        assert!(SeatPlan::new(&[], small).free_seats().is_empty());
+
+        let front = SeatPlan::new(&[BoardingPass::from_seat_id(1, small).unwrap()], small);
+        assert_eq!(vec![0, 2, 3], ids(front.free_seats()));
+        assert!(front.free_between_taken().is_empty());
+    }
 }
@@ -1,58 +1,166 @@
-use std::collections::*;
+use itertools::Itertools;
+use std::collections::HashSet;
+
+use crate::utils::*;
 
 const DAY: u32 = 6;
 
-type Input = Vec<Vec<HashSet<char>>>;
-type Output = usize;
+/// The questions an `AnswerSet` can hold, question `i` is stored in bit `i`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet(Vec<char>);
 
-fn input_transformer(input: &str) -> Input {
-    let mut groups: Vec<Vec<HashSet<char>>> = vec![];
-    let mut group: Vec<HashSet<char>> = vec![];
-    for l in input.lines().map(|l| l.trim()) {
-        if l.is_empty() && !group.is_empty() {
-            groups.push(group.clone());
-            group.clear();
-        } else if !l.is_empty() {
-            group.push(l.chars().collect())
+impl Alphabet {
+    pub fn new(questions: &str) -> Result<Self> {
+        let questions: Vec<char> = questions.chars().collect();
+        if questions.len() > 64 {
+            return Err(format!("{} questions do not fit into 64 bits", questions.len()).into());
         }
+        let mut seen = HashSet::new();
+        if let Some(c) = questions.iter().find(|&c| !seen.insert(c)) {
+            return Err(format!("question '{}' appears more than once", c).into());
+        }
+
+        Ok(Alphabet(questions))
     }
-    if !group.is_empty() {
-        groups.push(group);
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 
-    groups
+    pub fn parse(&self, answers: &str) -> Result<AnswerSet> {
+        answers.chars().try_fold(AnswerSet::default(), |set, c| {
+            match self.0.iter().position(|&q| q == c) {
+                Some(i) => Ok(set.union(AnswerSet(1 << i))),
+                None => Err(format!("'{}' is not a question", c).into()),
+            }
+        })
+    }
 }
 
-fn solve_part1(input: Input) -> Output {
-    input
+/// Questions `a` to `z`.
+impl Default for Alphabet {
+    fn default() -> Self {
+        Alphabet(('a'..='z').collect())
+    }
+}
+
+/// Questions answered "yes", as a bitset over an `Alphabet`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AnswerSet(u64);
+
+impl AnswerSet {
+    pub fn all(alphabet: &Alphabet) -> Self {
+        match alphabet.len() {
+            64 => AnswerSet(u64::MAX),
+            n => AnswerSet((1 << n) - 1),
+        }
+    }
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, question: usize) -> bool {
+        question < 64 && self.0 >> question & 1 == 1
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        AnswerSet(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        AnswerSet(self.0 & other.0)
+    }
+
+    pub fn symmetric_difference(self, other: Self) -> Self {
+        AnswerSet(self.0 ^ other.0)
+    }
+
+    /// Indices of the questions in the set, in alphabet order.
+    pub fn questions(self) -> impl Iterator<Item = usize> {
+        (0..64).filter(move |&q| self.contains(q))
+    }
+
+    pub fn to_string(self, alphabet: &Alphabet) -> String {
+        self.questions().map(|q| alphabet.0[q]).collect()
+    }
+}
+
+/// Questions answered by anyone in the group.
+pub fn anyone(group: &[AnswerSet]) -> AnswerSet {
+    group.iter().fold(AnswerSet::default(), |a, &b| a.union(b))
+}
+
+/// Questions answered by everyone in the group.
+pub fn everyone(group: &[AnswerSet]) -> AnswerSet {
+    group
         .iter()
-        .map(|g| {
-            g.iter().fold(None, |acc, p| -> Option<HashSet<char>> {
-                let p = p.iter().copied().collect();
-                acc.map(|a| a.union(&p).copied().collect()).or(Some(p))
-            })
-        })
-        .map(|g| g.unwrap().len())
-        .sum()
+        .copied()
+        .fold1(AnswerSet::intersection)
+        .unwrap_or_default()
+}
+
+/// Questions answered by at least `k` members of the group, `k = 0` counts
+/// as `1`.
+pub fn at_least(group: &[AnswerSet], k: usize) -> AnswerSet {
+    let mut counts = [0; 64];
+    for answers in group {
+        for q in answers.questions() {
+            counts[q] += 1;
+        }
+    }
+
+    (0..64)
+        .filter(|&q| counts[q] >= k.max(1))
+        .fold(AnswerSet::default(), |set, q| set.union(AnswerSet(1 << q)))
+}
+
+/// How many people answered each question, indexed by question.
+pub fn histogram(groups: &[Vec<AnswerSet>]) -> [usize; 64] {
+    let mut counts = [0; 64];
+    for answers in groups.iter().flatten() {
+        for q in answers.questions() {
+            counts[q] += 1;
+        }
+    }
+    counts
+}
+
+pub fn parse_groups(input: &str, alphabet: &Alphabet) -> Result<Vec<Vec<AnswerSet>>> {
+    paragraphs(input)
+        .map(|p| p.lines().map(|(_, l)| alphabet.parse(l)).collect())
+        .collect()
+}
+
+type Input = Vec<Vec<AnswerSet>>;
+type Output = usize;
+
+fn input_transformer(input: &str) -> Input {
+    parse_groups(input, &Alphabet::default()).unwrap()
+}
+
+fn solve_part1(input: Input) -> Output {
+    input.iter().map(|g| anyone(g).len()).sum()
 }
 
 fn solve_part2(input: Input) -> Output {
-    input
-        .iter()
-        .map(|g| {
-            g.iter().fold(None, |acc, p| -> Option<HashSet<char>> {
-                let p = p.iter().copied().collect();
-                acc.map(|a| a.intersection(&p).copied().collect())
-                    .or(Some(p))
-            })
-        })
-        .map(|g| g.unwrap().len())
-        .sum()
+    input.iter().map(|g| everyone(g).len()).sum()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{
+        anyone, at_least, everyone, histogram, input_transformer, parse_groups, solve_part1,
+        solve_part2, Alphabet, AnswerSet, DAY,
+    };
     use crate::utils::*;
 
     const SAMPLE: &str = "abc
@@ -100,4 +208,32 @@ mod tests {
 
         assert_eq!(3406, solution);
     }
+
+    #[test]
+    fn test_answer_set() {
+        let alphabet = Alphabet::default();
+        let group = input_transformer("abc\nabd\nbde").remove(0);
+
+        assert_eq!("abcde", anyone(&group).to_string(&alphabet));
+        assert_eq!("b", everyone(&group).to_string(&alphabet));
+        assert_eq!("abd", at_least(&group, 2).to_string(&alphabet));
+        assert_eq!(
+            "acde",
+            group[0].symmetric_difference(group[2]).to_string(&alphabet)
+        );
+        assert_eq!(26, AnswerSet::all(&alphabet).len());
+        assert!(everyone(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_custom_alphabet() {
+        let alphabet = Alphabet::new("xyz").unwrap();
+        let groups = parse_groups("xy\nzy\n\nz", &alphabet).unwrap();
+
+        let counts = histogram(&groups);
+        assert_eq!([1, 2, 2], counts[..3]);
+        assert_eq!("xyz", AnswerSet::all(&alphabet).to_string(&alphabet));
+        assert!(parse_groups("xa", &alphabet).is_err());
+        assert!(Alphabet::new("xyx").is_err());
+    }
 }
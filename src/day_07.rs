@@ -1,101 +1,333 @@
+use crate::utils::*;
 use itertools::Itertools;
+use std::cell::RefCell;
 use std::collections::*;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
 
 const DAY: u32 = 7;
 
-type Input = HashMap<String, HashMap<String, usize>>;
-type Output = usize;
+pub type BagId = usize;
 
-fn input_transformer(input: &str) -> Input {
-    input
-        .lines()
-        .map(|l| {
-            l.replace("bags", "")
-                .replace("bag", "")
-                .replace(".", "")
-                .trim()
-                .to_string()
-        })
-        .map(|l| {
-            let (color, contains) = l
-                .split("contain")
-                .map(|x| x.trim())
-                .collect_tuple()
-                .unwrap();
-            let contains: HashMap<String, usize> = contains
-                .split(',')
-                .filter(|x| *x != "no other")
-                .map(|x| {
-                    let (count, color) = x.trim().split_once(' ').unwrap();
-                    let count: usize = count.parse().unwrap();
-                    (color.to_string(), count)
-                })
-                .collect();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleError {
+    /// Colours along the cycle, starting and ending with the same one.
+    pub cycle: Vec<String>,
+}
 
-            (color.to_owned(), contains)
-        })
-        .collect()
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bags contain themselves: {}", self.cycle.join(" -> "))
+    }
 }
 
-fn solve_part1(input: Input) -> Output {
-    let mut solution = hashset![];
-
-    let mut already_searched = hashset![];
-    let mut search_colors = hashset!["shiny gold".to_string()];
-    while !search_colors.is_empty() {
-        let mut next_search_colors = hashset![];
-        for (color, contains) in input.iter() {
-            let contains_keys: HashSet<String> = contains.keys().cloned().collect();
-            if search_colors.intersection(&contains_keys).count() > 0 {
-                solution.insert(color.to_owned());
-                if !already_searched.contains(color) {
-                    next_search_colors.insert(color.to_owned());
+impl std::error::Error for CycleError {}
+
+/// Bag rules with colours interned as ids. Query results are memoised until
+/// the next edge is added.
+#[derive(Debug, Clone, Default)]
+pub struct BagGraph {
+    colors: Vec<String>,
+    ids: HashMap<String, BagId>,
+    contains: Vec<Vec<(BagId, usize)>>,
+    contained_in: Vec<Vec<(BagId, usize)>>,
+    inside: RefCell<HashMap<BagId, usize>>,
+    containers: RefCell<HashMap<BagId, HashSet<BagId>>>,
+}
+
+impl BagGraph {
+    fn intern(&mut self, color: &str) -> BagId {
+        if let Some(&id) = self.ids.get(color) {
+            return id;
+        }
+
+        let id = self.colors.len();
+        self.colors.push(color.to_owned());
+        self.ids.insert(color.to_owned(), id);
+        self.contains.push(vec![]);
+        self.contained_in.push(vec![]);
+        id
+    }
+
+    /// Adds `count` bags of colour `inner` to the contents of `outer`.
+    pub fn add_edge(&mut self, outer: &str, inner: &str, count: usize) {
+        let (outer, inner) = (self.intern(outer), self.intern(inner));
+        self.contains[outer].push((inner, count));
+        self.contained_in[inner].push((outer, count));
+        self.inside.get_mut().clear();
+        self.containers.get_mut().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn id(&self, color: &str) -> Option<BagId> {
+        self.ids.get(color).copied()
+    }
+
+    pub fn color(&self, id: BagId) -> &str {
+        &self.colors[id]
+    }
+
+    /// Bags directly inside `id`, with their counts.
+    pub fn contents(&self, id: BagId) -> &[(BagId, usize)] {
+        &self.contains[id]
+    }
+
+    /// Bags directly holding `id`, with how many of it they hold.
+    pub fn holders(&self, id: BagId) -> &[(BagId, usize)] {
+        &self.contained_in[id]
+    }
+
+    /// Every bag before the bags inside it.
+    pub fn topological_order(&self) -> std::result::Result<Vec<BagId>, CycleError> {
+        let mut holders: Vec<usize> = self.contained_in.iter().map(|h| h.len()).collect();
+        let mut order: Vec<BagId> = (0..self.len()).filter(|&id| holders[id] == 0).collect();
+
+        let mut i = 0;
+        while i < order.len() {
+            for &(inner, _) in &self.contains[order[i]] {
+                holders[inner] -= 1;
+                if holders[inner] == 0 {
+                    order.push(inner);
                 }
             }
+            i += 1;
+        }
+
+        if order.len() == self.len() {
+            return Ok(order);
         }
-        already_searched = already_searched.union(&search_colors).cloned().collect();
-        search_colors = next_search_colors;
+
+        // Every bag left is held by another one left, so walking up the
+        // holders must come back around
+        let left = |id: &BagId| holders[*id] > 0;
+        let mut path = vec![(0..self.len()).find(left).unwrap()];
+        loop {
+            let last = *path.last().unwrap();
+            let next = self.contained_in[last]
+                .iter()
+                .map(|(h, _)| h)
+                .find(|h| left(h));
+            let next = *next.unwrap();
+            if let Some(start) = path.iter().position(|&id| id == next) {
+                // The path goes from held to holder bags, the cycle the other way
+                let mut cycle: Vec<String> = path[start..]
+                    .iter()
+                    .rev()
+                    .map(|&id| self.colors[id].to_owned())
+                    .collect();
+                cycle.push(cycle[0].to_owned());
+                return Err(CycleError { cycle });
+            }
+            path.push(next);
+        }
+    }
+
+    fn resolve(&self, color: &str) -> Result<BagId> {
+        self.id(color)
+            .ok_or_else(|| format!("no rule mentions '{}' bags", color).into())
+    }
+
+    /// How many bags a `color` bag holds, counting nested bags. Fails only
+    /// when a bag inside the `color` bag eventually holds itself.
+    pub fn bags_inside(&self, color: &str) -> Result<usize> {
+        let id = self.resolve(color)?;
+        Ok(self.count_inside(id, &mut vec![])?)
     }
 
-    solution.len()
+    /// Depth-first count memoised across queries, `path` holds the bags
+    /// being counted from the queried one down to `id`.
+    fn count_inside(
+        &self,
+        id: BagId,
+        path: &mut Vec<BagId>,
+    ) -> std::result::Result<usize, CycleError> {
+        if let Some(&inside) = self.inside.borrow().get(&id) {
+            return Ok(inside);
+        }
+        if let Some(start) = path.iter().position(|&p| p == id) {
+            let mut cycle: Vec<String> = path[start..]
+                .iter()
+                .map(|&id| self.colors[id].to_owned())
+                .collect();
+            cycle.push(cycle[0].to_owned());
+            return Err(CycleError { cycle });
+        }
+
+        path.push(id);
+        let mut inside = 0;
+        for &(inner, count) in &self.contains[id] {
+            inside += count * (1 + self.count_inside(inner, path)?);
+        }
+        path.pop();
+
+        self.inside.borrow_mut().insert(id, inside);
+        Ok(inside)
+    }
+
+    /// Colours of every bag that eventually holds a `color` bag, sorted. Well
+    /// defined even if bags hold themselves, so this never fails on cycles.
+    pub fn containers_of(&self, color: &str) -> Result<Vec<&str>> {
+        let id = self.resolve(color)?;
+        let mut containers = self.containers.borrow_mut();
+        let containers = containers.entry(id).or_insert_with(|| {
+            let mut seen = HashSet::new();
+            let mut queue = vec![id];
+            while let Some(inner) = queue.pop() {
+                for &(outer, _) in &self.contained_in[inner] {
+                    if seen.insert(outer) {
+                        queue.push(outer);
+                    }
+                }
+            }
+            seen
+        });
+
+        Ok(containers
+            .iter()
+            .map(|&id| self.color(id))
+            .sorted()
+            .collect())
+    }
 }
 
-fn solve_part2(input: Input) -> Output {
-    let mut searched: HashMap<String, usize> = input
-        .iter()
-        .filter(|(_, v)| v.is_empty())
-        .map(|(k, v)| (k.to_string(), 0))
-        .collect();
-
-    let input_keys: HashSet<String> = input.keys().cloned().collect();
-    loop {
-        let searched_keys: HashSet<String> = searched.keys().cloned().collect();
-        for color in input_keys.difference(&searched_keys) {
-            let color_keys: HashSet<String> = input.get(color).unwrap().keys().cloned().collect();
-            if color_keys.difference(&searched_keys).count() > 0 {
-                continue;
+impl BagGraph {
+    /// Graphviz digraph with an edge from every bag to the bags it holds,
+    /// labelled and weighted by the count.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph bags {\n");
+        for (id, color) in self.colors.iter().enumerate() {
+            dot.push_str(&format!("    \"{}\";\n", color));
+            for &(inner, count) in &self.contains[id] {
+                dot.push_str(&format!(
+                    "    \"{}\" -> \"{}\" [label=\"{}\", weight={}];\n",
+                    color, self.colors[inner], count, count
+                ));
             }
+        }
+        dot.push_str("}\n");
 
-            print!("{:?} = ", color);
-            let n = input.get(color).unwrap().iter().fold(0, |acc, (k, v)| {
-                let x = searched.get(k).unwrap();
-                print!("({} + {} * {}) + ", v, v, x);
-                acc + v + v * x
-            });
-            println!("0 = {:?}", n);
+        dot
+    }
 
-            searched.insert(color.to_owned(), n);
+    pub fn write_dot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_dot())
+    }
 
-            if color == "shiny gold" {
-                return searched.get(color).unwrap().to_owned();
+    /// The bags inside a `color` bag as an indented tree, each line with the
+    /// total number of such bags at that spot. Bags deeper than `max_depth`
+    /// are left out and their holder marked with `...`, so `Some(0)` renders
+    /// the `color` bag alone.
+    pub fn render_tree(&self, color: &str, max_depth: Option<usize>) -> Result<String> {
+        let id = self.resolve(color)?;
+        if max_depth.is_none() {
+            self.count_inside(id, &mut vec![])?;
+        }
+
+        if max_depth == Some(0) && !self.contains[id].is_empty() {
+            return Ok(format!("{} ...\n", color));
+        }
+
+        let mut tree = format!("{}\n", color);
+        self.render_contents(id, 1, "", 1, max_depth, &mut tree);
+        Ok(tree)
+    }
+
+    fn render_contents(
+        &self,
+        id: BagId,
+        multiplier: usize,
+        prefix: &str,
+        depth: usize,
+        max_depth: Option<usize>,
+        tree: &mut String,
+    ) {
+        let contents = &self.contains[id];
+        for (i, &(inner, count)) in contents.iter().enumerate() {
+            let last = i + 1 == contents.len();
+            let total = multiplier * count;
+            let cut = max_depth.is_some_and(|m| depth >= m) && !self.contains[inner].is_empty();
+            tree.push_str(&format!(
+                "{}{}{} {}{}\n",
+                prefix,
+                if last { "└── " } else { "├── " },
+                total,
+                self.colors[inner],
+                if cut { " ..." } else { "" }
+            ));
+            if !cut {
+                let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                self.render_contents(inner, total, &prefix, depth + 1, max_depth, tree);
             }
         }
     }
+
+    pub fn write_tree<P: AsRef<Path>>(
+        &self,
+        path: P,
+        color: &str,
+        max_depth: Option<usize>,
+    ) -> Result<()> {
+        Ok(fs::write(path, self.render_tree(color, max_depth)?)?)
+    }
+}
+
+impl FromStr for BagGraph {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut graph = BagGraph::default();
+        for l in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (outer, contents) = l
+                .trim_end_matches('.')
+                .split_once(" bags contain ")
+                .ok_or_else(|| format!("expected '<color> bags contain ...', got '{}'", l))?;
+            graph.intern(outer);
+            if contents == "no other bags" {
+                continue;
+            }
+
+            for content in contents.split(", ") {
+                let (count, inner) = content
+                    .trim_end_matches(" bags")
+                    .trim_end_matches(" bag")
+                    .split_once(' ')
+                    .ok_or_else(|| format!("expected '<count> <color> bags', got '{}'", content))?;
+                graph.add_edge(outer, inner, count.parse()?);
+            }
+        }
+
+        Ok(graph)
+    }
+}
+
+type Input = BagGraph;
+type Output = usize;
+
+fn input_transformer(input: &str) -> Input {
+    input.parse().unwrap()
+}
+
+fn solve_part1(input: Input) -> Output {
+    input.containers_of("shiny gold").unwrap().len()
+}
+
+fn solve_part2(input: Input) -> Output {
+    input.bags_inside("shiny gold").unwrap()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{input_transformer, solve_part1, solve_part2, BagGraph, CycleError, DAY};
     use crate::utils::*;
 
     const SAMPLE: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
@@ -151,4 +383,134 @@ mod tests {
 
         assert_eq!(12128, solution);
     }
+
+    #[test]
+    fn test_queries_for_any_color() {
+        let graph = input_transformer(SAMPLE);
+
+        assert_eq!(9, graph.len());
+        assert_eq!(7, graph.bags_inside("dark olive").unwrap());
+        assert_eq!(0, graph.bags_inside("faded blue").unwrap());
+        assert_eq!(
+            vec![
+                "bright white",
+                "dark orange",
+                "light red",
+                "muted yellow",
+                "shiny gold"
+            ],
+            graph.containers_of("dark olive").unwrap()
+        );
+        assert!(graph.containers_of("light red").unwrap().is_empty());
+        assert!(graph.bags_inside("plaid").is_err());
+
+        let order = graph.topological_order().unwrap();
+        let position = |color| order.iter().position(|&id| graph.color(id) == color);
+        assert!(position("light red") < position("shiny gold"));
+        assert!(position("shiny gold") < position("dotted black"));
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let graph: BagGraph = "light red bags contain 1 dark blue bag.
+            dark blue bags contain 2 pale green bags, 1 faded blue bag.
+            pale green bags contain 1 light red bag.
+            faded blue bags contain no other bags."
+            .parse()
+            .unwrap();
+
+        let error = graph.topological_order().unwrap_err();
+        assert_eq!(
+            CycleError {
+                cycle: vec![
+                    "dark blue".to_string(),
+                    "pale green".to_string(),
+                    "light red".to_string(),
+                    "dark blue".to_string()
+                ]
+            },
+            error
+        );
+        assert_eq!(
+            "bags contain themselves: light red -> dark blue -> pale green -> light red",
+            graph.bags_inside("light red").unwrap_err().to_string()
+        );
+
+        // Queries not reaching the cycle are still answered
+        assert_eq!(0, graph.bags_inside("faded blue").unwrap());
+        assert_eq!(
+            vec!["dark blue", "light red", "pale green"],
+            graph.containers_of("faded blue").unwrap()
+        );
+        assert_eq!(
+            "faded blue\n",
+            graph.render_tree("faded blue", None).unwrap()
+        );
+        assert!("light red bags hold 1 dark blue bag."
+            .parse::<BagGraph>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_dot_export() {
+        let graph = input_transformer(SAMPLE);
+        let dot = graph.to_dot();
+
+        assert!(dot.starts_with("digraph bags {\n    \"light red\";\n"));
+        assert!(dot.contains("    \"muted yellow\" -> \"faded blue\" [label=\"9\", weight=9];\n"));
+        assert_eq!(13, dot.matches(" -> ").count());
+
+        assert_eq!(
+            dot,
+            read_written("day_07.dot", |path| graph.write_dot(path))
+        );
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let graph = input_transformer(SAMPLE);
+
+        assert_eq!(
+            "shiny gold
+├── 1 dark olive
+│   ├── 3 faded blue
+│   └── 4 dotted black
+└── 2 vibrant plum
+    ├── 10 faded blue
+    └── 12 dotted black
+",
+            graph.render_tree("shiny gold", None).unwrap()
+        );
+        assert_eq!(
+            "light red\n├── 1 bright white ...\n└── 2 muted yellow ...\n",
+            graph.render_tree("light red", Some(1)).unwrap()
+        );
+
+        assert_eq!(
+            "faded blue\n",
+            read_written("day_07.txt", |path| graph.write_tree(
+                path,
+                "faded blue",
+                Some(3)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_render_tree_depth_limit_on_cycle() {
+        let graph: BagGraph = "light red bags contain 1 dark blue bag.
+            dark blue bags contain 2 light red bags."
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            "light red ...\n",
+            graph.render_tree("light red", Some(0)).unwrap()
+        );
+        assert_eq!(
+            "light red\n└── 1 dark blue\n    └── 2 light red ...\n",
+            graph.render_tree("light red", Some(2)).unwrap()
+        );
+        assert!(graph.render_tree("light red", None).is_err());
+    }
 }
@@ -1,4 +1,7 @@
 use itertools::Itertools;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
 
 use crate::utils::*;
 
@@ -8,64 +11,122 @@ fn input_transformer(input: &str) -> Vec<i64> {
     parse_input(input)
 }
 
-// O(n^2 log n)
-fn solve_part1(input: &[i64], preamble_size: usize) -> Option<i64> {
-    let mut preamble: Vec<i64> = input[..preamble_size].to_vec();
-    preamble.sort_unstable();
+/// Counts of the sums of every pair of numbers in a sliding window, so a
+/// number entering or leaving only touches its own pairs.
+#[derive(Debug, Clone, Default)]
+struct PairSums {
+    window: VecDeque<i64>,
+    sums: HashMap<i64, usize>,
+}
 
-    for i in preamble_size..input.len() {
-        if !is_ok(&preamble, input[i]) {
-            return Some(input[i]);
+impl PairSums {
+    fn push(&mut self, n: i64) {
+        for &m in &self.window {
+            *self.sums.entry(n + m).or_insert(0) += 1;
         }
+        self.window.push_back(n);
+    }
 
-        preamble.swap_remove(preamble.binary_search(&input[i - preamble_size]).unwrap());
-        preamble.push(input[i]);
-        preamble.sort_unstable();
+    fn pop(&mut self) {
+        if let Some(n) = self.window.pop_front() {
+            for &m in &self.window {
+                if let Entry::Occupied(mut e) = self.sums.entry(n + m) {
+                    *e.get_mut() -= 1;
+                    if *e.get() == 0 {
+                        e.remove();
+                    }
+                }
+            }
+        }
+    }
+
+    fn contains(&self, sum: i64) -> bool {
+        self.sums.contains_key(&sum)
     }
-    None
 }
 
-// O(n)
-fn solve_part2(input: &[i64], target_sum: i64) -> Option<i64> {
-    let mut l = 0;
-    let mut r = 1;
-    let mut sum = input[l] + input[r];
-    while l < input.len() && r < input.len() {
-        match sum {
-            x if x < target_sum => {
-                r += 1;
-                sum += input[r];
-            }
-            x if x > target_sum => {
-                sum -= input[l];
-                l += 1;
-            }
-            _ => {
-                let (min, max) = input[l..r + 1].iter().minmax().into_option().unwrap();
-                return Some(min + max);
+#[derive(Debug, Clone, Copy)]
+pub struct XmasCipher<'a> {
+    input: &'a [i64],
+    preamble: usize,
+}
+
+impl<'a> XmasCipher<'a> {
+    pub fn new(input: &'a [i64], preamble: usize) -> Self {
+        XmasCipher { input, preamble }
+    }
+
+    /// Every number after the preamble that is not the sum of two of the
+    /// `preamble` numbers before it, with its index.
+    ///
+    /// Time:   O(n * preamble)
+    pub fn invalid_numbers(&self) -> Vec<(usize, i64)> {
+        let mut sums = PairSums::default();
+        let mut invalid = vec![];
+        for (i, &n) in self.input.iter().enumerate() {
+            if i >= self.preamble {
+                if !sums.contains(n) {
+                    invalid.push((i, n));
+                }
+                sums.pop();
             }
+            sums.push(n);
         }
+
+        invalid
     }
-    None
-}
 
-fn is_ok(input: &[i64], target_sum: i64) -> bool {
-    let mut l = 0;
-    let mut r = input.len() - 1;
-    while l < r {
-        match input[l] + input[r] {
-            sum if sum == target_sum => return true,
-            sum if sum < target_sum => l += 1,
-            _ => r -= 1,
+    pub fn first_invalid(&self) -> Option<i64> {
+        self.invalid_numbers().first().map(|&(_, n)| n)
+    }
+
+    /// Every range of at least two contiguous numbers that adds to the
+    /// `target_sum`, ordered by start.
+    ///
+    /// Time:   O(n + ranges)
+    pub fn ranges_summing_to(&self, target_sum: i64) -> Vec<Range<usize>> {
+        // Ends of the prefixes, by prefix sum
+        let mut prefixes: HashMap<i64, Vec<usize>> = HashMap::new();
+        let mut ranges = vec![];
+        let mut sum = 0;
+        prefixes.entry(0).or_default().push(0);
+        for (i, &n) in self.input.iter().enumerate() {
+            sum += n;
+            if let Some(starts) = prefixes.get(&(sum - target_sum)) {
+                ranges.extend(
+                    starts
+                        .iter()
+                        .filter(|&&s| s + 2 <= i + 1)
+                        .map(|&s| s..i + 1),
+                );
+            }
+            prefixes.entry(sum).or_default().push(i + 1);
         }
+
+        ranges.sort_by_key(|r| (r.start, r.end));
+        ranges
+    }
+
+    /// Sum of the smallest and largest number of the first range adding to
+    /// the `target_sum`.
+    pub fn weakness(&self, target_sum: i64) -> Option<i64> {
+        let range = self.ranges_summing_to(target_sum).into_iter().next()?;
+        let (min, max) = self.input[range].iter().minmax().into_option()?;
+        Some(min + max)
     }
+}
+
+fn solve_part1(input: &[i64], preamble_size: usize) -> Option<i64> {
+    XmasCipher::new(input, preamble_size).first_invalid()
+}
 
-    false
+fn solve_part2(input: &[i64], target_sum: i64) -> Option<i64> {
+    XmasCipher::new(input, 0).weakness(target_sum)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{input_transformer, solve_part1, solve_part2, XmasCipher, DAY};
     use crate::utils::*;
 
     const SAMPLE: &str = "35
@@ -118,4 +179,34 @@ mod tests {
 
         assert_eq!(Some(169521051), solution);
     }
+
+    #[test]
+    fn test_all_invalid_numbers() {
+        let input = input_transformer(SAMPLE);
+        let cipher = XmasCipher::new(&input, 5);
+
+        assert_eq!(vec![(14, 127)], cipher.invalid_numbers());
+        // Equal numbers at different positions still form a pair
+        assert_eq!(
+            vec![(5, 1)],
+            XmasCipher::new(&[2, 2, 3, 4, 7, 1], 3).invalid_numbers()
+        );
+        assert!(XmasCipher::new(&[1, 2], 5).invalid_numbers().is_empty());
+    }
+
+    #[test]
+    fn test_ranges_summing_to() {
+        let input = input_transformer(SAMPLE);
+        let cipher = XmasCipher::new(&input, 5);
+        assert_eq!(vec![2..6], cipher.ranges_summing_to(127));
+
+        let cipher = XmasCipher::new(&[1, 2, 3, 0, 3, -3, 6], 0);
+        assert_eq!(
+            vec![0..3, 0..4, 0..6, 2..5, 3..7, 4..7],
+            cipher.ranges_summing_to(6)
+        );
+        // The last number alone is not a range
+        assert!(XmasCipher::new(&[1, 5], 0).ranges_summing_to(5).is_empty());
+        assert_eq!(None, XmasCipher::new(&[], 0).weakness(5));
+    }
 }
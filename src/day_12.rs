@@ -1,86 +1,321 @@
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+
 const DAY: u32 = 12;
 
-type Input = Vec<(char, i32)>;
-type Output = i32;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NavInstruction {
+    North(f64),
+    South(f64),
+    East(f64),
+    West(f64),
+    /// Counter-clockwise turn in degrees.
+    Left(f64),
+    /// Clockwise turn in degrees.
+    Right(f64),
+    Forward(f64),
+}
 
-fn input_transformer(input: &str) -> Input {
-    input
-        .lines()
-        .map(|l| l.trim())
-        .map(|l| {
-            let mut action = l.chars().next().unwrap();
-            let mut value = l[1..].parse::<i32>().unwrap();
-            if action == 'L' || action == 'R' {
-                value %= 360;
-            }
-            if action == 'L' {
-                action = 'R';
-                value = 360 - value;
-            }
-            (action, value)
+#[derive(Debug, Clone, PartialEq)]
+pub enum NavError {
+    Empty,
+    UnknownAction(char),
+    InvalidValue(String),
+}
+
+impl fmt::Display for NavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavError::Empty => write!(f, "empty instruction"),
+            NavError::UnknownAction(c) => write!(f, "unknown action '{}'", c),
+            NavError::InvalidValue(v) => write!(f, "invalid value '{}'", v),
+        }
+    }
+}
+
+impl std::error::Error for NavError {}
+
+impl FromStr for NavInstruction {
+    type Err = NavError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let action = s.chars().next().ok_or(NavError::Empty)?;
+        let value = &s[action.len_utf8()..];
+        let value: f64 = match value.parse() {
+            Ok(v) if f64::is_finite(v) => v,
+            _ => return Err(NavError::InvalidValue(value.to_owned())),
+        };
+
+        Ok(match action {
+            'N' => NavInstruction::North(value),
+            'S' => NavInstruction::South(value),
+            'E' => NavInstruction::East(value),
+            'W' => NavInstruction::West(value),
+            'L' => NavInstruction::Left(value),
+            'R' => NavInstruction::Right(value),
+            'F' => NavInstruction::Forward(value),
+            c => return Err(NavError::UnknownAction(c)),
         })
-        .collect()
+    }
 }
 
-fn solve_part1(input: &Input) -> Output {
-    let mut position: (i32, i32, i32) = (0, 0, 90);
-    for (action, value) in input {
-        match action {
-            'N' => position.1 += *value,
-            'S' => position.1 -= *value,
-            'E' => position.0 += *value,
-            'W' => position.0 -= *value,
-            'R' => position.2 = (360 + position.2 + value) % 360,
-            'F' => match position.2 {
-                0 => position.1 += *value,
-                180 => position.1 -= *value,
-                90 => position.0 += *value,
-                270 => position.0 -= *value,
-                _ => panic!("Invalid action '{}{}' @{:?}!", action, value, position),
-            },
-            _ => panic!("Invalid action '{}{}' @{:?}!", action, value, position),
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Vector {
+    pub const fn new(x: f64, y: f64) -> Self {
+        Vector { x, y }
+    }
+
+    /// Counter-clockwise rotation, quarter turns are exact.
+    pub fn rotate(self, degrees: f64) -> Self {
+        let quarters = degrees / 90.0;
+        if quarters.fract() == 0.0 {
+            return match quarters.rem_euclid(4.0) as u8 {
+                0 => self,
+                1 => Vector::new(-self.y, self.x),
+                2 => Vector::new(-self.x, -self.y),
+                _ => Vector::new(self.y, -self.x),
+            };
+        }
+
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Vector::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+
+    pub fn manhattan(self) -> f64 {
+        self.x.abs() + self.y.abs()
+    }
+}
+
+impl Add for Vector {
+    type Output = Vector;
+
+    fn add(self, other: Vector) -> Vector {
+        Vector::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Mul<f64> for Vector {
+    type Output = Vector;
+
+    fn mul(self, k: f64) -> Vector {
+        Vector::new(self.x * k, self.y * k)
+    }
+}
+
+const NORTH: Vector = Vector::new(0.0, 1.0);
+const SOUTH: Vector = Vector::new(0.0, -1.0);
+const EAST: Vector = Vector::new(1.0, 0.0);
+const WEST: Vector = Vector::new(-1.0, 0.0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ship {
+    pub position: Vector,
+    /// Unit vector the ship faces.
+    pub heading: Vector,
+    /// Relative to the ship.
+    pub waypoint: Vector,
+}
+
+impl Default for Ship {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ship {
+    /// At the origin facing east, with the waypoint 10 east and 1 north.
+    pub fn new() -> Self {
+        Ship {
+            position: Vector::new(0.0, 0.0),
+            heading: EAST,
+            waypoint: Vector::new(10.0, 1.0),
         }
     }
 
-    position.0.abs() + position.1.abs()
+    /// Moves the ship itself, turns change its heading.
+    pub fn navigate(&mut self, instruction: &NavInstruction) {
+        match *instruction {
+            NavInstruction::North(v) => self.position = self.position + NORTH * v,
+            NavInstruction::South(v) => self.position = self.position + SOUTH * v,
+            NavInstruction::East(v) => self.position = self.position + EAST * v,
+            NavInstruction::West(v) => self.position = self.position + WEST * v,
+            NavInstruction::Left(v) => self.heading = self.heading.rotate(v),
+            NavInstruction::Right(v) => self.heading = self.heading.rotate(-v),
+            NavInstruction::Forward(v) => self.position = self.position + self.heading * v,
+        }
+    }
+
+    /// Moves and rotates the waypoint, the ship only moves forward towards it.
+    pub fn navigate_waypoint(&mut self, instruction: &NavInstruction) {
+        match *instruction {
+            NavInstruction::North(v) => self.waypoint = self.waypoint + NORTH * v,
+            NavInstruction::South(v) => self.waypoint = self.waypoint + SOUTH * v,
+            NavInstruction::East(v) => self.waypoint = self.waypoint + EAST * v,
+            NavInstruction::West(v) => self.waypoint = self.waypoint + WEST * v,
+            NavInstruction::Left(v) => self.waypoint = self.waypoint.rotate(v),
+            NavInstruction::Right(v) => self.waypoint = self.waypoint.rotate(-v),
+            NavInstruction::Forward(v) => self.position = self.position + self.waypoint * v,
+        }
+    }
 }
 
-fn solve_part2(input: &Input) -> Output {
-    let mut waypoint: (i32, i32) = (10, 1);
-    let mut position: (i32, i32) = (0, 0);
-    for (action, value) in input {
-        match action {
-            'N' => waypoint.1 += *value,
-            'S' => waypoint.1 -= *value,
-            'E' => waypoint.0 += *value,
-            'W' => waypoint.0 -= *value,
-            'R' => match value {
-                0 => (),
-                90 => waypoint = (waypoint.1, -waypoint.0),
-                180 => waypoint = (-waypoint.0, -waypoint.1),
-                270 => waypoint = (-waypoint.1, waypoint.0),
-                _ => panic!(
-                    "Invalid action '{}{}' @{:?}>{:?}!",
-                    action, value, position, waypoint
-                ),
-            },
-            'F' => {
-                position.0 += waypoint.0 * *value;
-                position.1 += waypoint.1 * *value;
+/// Polylines of the ship and, when navigating by it, the waypoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub ship: Vec<Vector>,
+    /// Absolute waypoint positions, empty unless navigating by waypoint.
+    pub waypoint: Vec<Vector>,
+}
+
+impl Route {
+    pub fn trace(input: &[NavInstruction], by_waypoint: bool) -> Self {
+        let mut ship = Ship::new();
+        let mut route = Route {
+            ship: vec![ship.position],
+            waypoint: vec![],
+        };
+        if by_waypoint {
+            route.waypoint.push(ship.position + ship.waypoint);
+        }
+
+        for instruction in input {
+            if by_waypoint {
+                ship.navigate_waypoint(instruction);
+                push_point(&mut route.waypoint, ship.position + ship.waypoint);
+            } else {
+                ship.navigate(instruction);
             }
-            _ => panic!(
-                "Invalid action '{}{}' @{:?}>{:?}!",
-                action, value, position, waypoint
-            ),
+            push_point(&mut route.ship, ship.position);
         }
+
+        route
+    }
+
+    /// Lower left and upper right corner around both polylines.
+    pub fn bounding_box(&self) -> (Vector, Vector) {
+        let first = self.ship[0];
+        self.ship
+            .iter()
+            .chain(&self.waypoint)
+            .fold((first, first), |(lo, hi), p| {
+                (
+                    Vector::new(lo.x.min(p.x), lo.y.min(p.y)),
+                    Vector::new(hi.x.max(p.x), hi.y.max(p.y)),
+                )
+            })
     }
 
-    position.0.abs() + position.1.abs()
+    /// Euclidean length of the ship polyline.
+    pub fn distance(&self) -> f64 {
+        self.ship
+            .windows(2)
+            .map(|w| (w[1].x - w[0].x).hypot(w[1].y - w[0].y))
+            .sum()
+    }
+
+    /// Plots the route with north up, the ship in blue and the waypoint dashed
+    /// in orange.
+    pub fn to_svg(&self) -> String {
+        let (lo, hi) = self.bounding_box();
+        let margin = ((hi.x - lo.x).max(hi.y - lo.y) / 20.0).ceil().max(1.0);
+        let points = |line: &[Vector]| {
+            line.iter()
+                .map(|p| format!("{},{}", p.x, 0.0 - p.y))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            lo.x - margin,
+            -hi.y - margin,
+            hi.x - lo.x + 2.0 * margin,
+            hi.y - lo.y + 2.0 * margin
+        );
+        let stroke = margin / 4.0;
+        if !self.waypoint.is_empty() {
+            svg.push_str(&format!(
+                "  <polyline class=\"waypoint\" points=\"{}\" fill=\"none\" stroke=\"orange\" stroke-width=\"{}\" stroke-dasharray=\"{}\"/>\n",
+                points(&self.waypoint),
+                stroke,
+                stroke * 4.0
+            ));
+        }
+        svg.push_str(&format!(
+            "  <polyline class=\"ship\" points=\"{}\" fill=\"none\" stroke=\"blue\" stroke-width=\"{}\"/>\n",
+            points(&self.ship),
+            stroke
+        ));
+        for (class, p) in [
+            ("start", self.ship[0]),
+            ("end", self.ship[self.ship.len() - 1]),
+        ] {
+            svg.push_str(&format!(
+                "  <circle class=\"{}\" cx=\"{}\" cy=\"{}\" r=\"{}\"/>\n",
+                class,
+                p.x,
+                0.0 - p.y,
+                stroke * 2.0
+            ));
+        }
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+
+    pub fn write_svg<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_svg())
+    }
+}
+
+/// Appends the point unless the route already ends there.
+fn push_point(line: &mut Vec<Vector>, point: Vector) {
+    if line.last() != Some(&point) {
+        line.push(point);
+    }
+}
+
+type Input = Vec<NavInstruction>;
+type Output = f64;
+
+/// Parses the instructions, errors carry the 1-based line number.
+fn input_transformer(input: &str) -> Result<Input, (usize, NavError)> {
+    input
+        .lines()
+        .map(|l| l.trim())
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty())
+        .map(|(i, l)| l.parse().map_err(|e| (i + 1, e)))
+        .collect()
+}
+
+fn solve_part1(input: &Input) -> Output {
+    let mut ship = Ship::new();
+    input.iter().for_each(|i| ship.navigate(i));
+
+    ship.position.manhattan()
+}
+
+fn solve_part2(input: &Input) -> Output {
+    let mut ship = Ship::new();
+    input.iter().for_each(|i| ship.navigate_waypoint(i));
+
+    ship.position.manhattan()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{
+        input_transformer, solve_part1, solve_part2, NavError, NavInstruction, Route, Vector, DAY,
+    };
     use crate::utils::*;
 
     const SAMPLE: &str = "F10
@@ -99,31 +334,93 @@ mod tests {
 
     #[test]
     fn test_part1_sample() {
-        let solution = solve_part1(&input_transformer(SAMPLE));
+        let solution = solve_part1(&input_transformer(SAMPLE).unwrap());
 
-        assert_eq!(25, solution);
+        assert_eq!(25.0, solution);
     }
 
     #[test]
     fn test_part1_puzzle() {
         let input = read_input(2020, DAY).unwrap();
-        let solution = solve_part1(&input_transformer(&input));
+        let solution = solve_part1(&input_transformer(&input).unwrap());
 
-        assert_eq!(1687, solution);
+        assert_eq!(1687.0, solution);
     }
 
     #[test]
     fn test_part2_sample() {
-        let solution = solve_part2(&input_transformer(SAMPLE));
+        let solution = solve_part2(&input_transformer(SAMPLE).unwrap());
 
-        assert_eq!(286, solution);
+        assert_eq!(286.0, solution);
     }
 
     #[test]
     fn test_part2_puzzle() {
         let input = read_input(2020, DAY).unwrap();
-        let solution = solve_part2(&input_transformer(&input));
+        let solution = solve_part2(&input_transformer(&input).unwrap());
+
+        assert_eq!(20873.0, solution);
+    }
+
+    #[test]
+    fn test_non_right_angles() {
+        let input = input_transformer("R45\nF10\nL135\nF10").unwrap();
+
+        // Ends at (5√2, 10 - 5√2) after heading south-east and then north
+        assert!((10.0 - solve_part1(&input)).abs() < 1e-9);
+        assert_eq!(Some(&NavInstruction::Left(135.0)), input.get(2));
+    }
+
+    #[test]
+    fn test_malformed_instructions() {
+        assert_eq!(
+            Err((2, NavError::UnknownAction('X'))),
+            input_transformer("F10\nX3")
+        );
+        assert_eq!(
+            Err((1, NavError::InvalidValue("ten".to_string()))),
+            input_transformer("Ften")
+        );
+        assert_eq!(
+            "invalid value ''",
+            "N".parse::<NavInstruction>().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_route() {
+        let route = Route::trace(&input_transformer(SAMPLE).unwrap(), false);
+
+        assert_eq!(Vector::new(0.0, 0.0), route.ship[0]);
+        assert_eq!(Vector::new(17.0, -8.0), route.ship[route.ship.len() - 1]);
+        assert!(route.waypoint.is_empty());
+        assert_eq!(
+            (Vector::new(0.0, -8.0), Vector::new(17.0, 3.0)),
+            route.bounding_box()
+        );
+        assert_eq!(59.0, route.distance());
+    }
+
+    #[test]
+    fn test_route_svg() {
+        let route = Route::trace(&input_transformer("F10\nN3\nF7\nR90\nF11").unwrap(), true);
+        let svg = route.to_svg();
+
+        assert_eq!(4, route.ship.len());
+        assert!(svg
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-11 -53 240 146\">"));
+        assert!(svg.contains(
+            "<polyline class=\"ship\" points=\"0,0 100,-10 170,-38 214,72\" fill=\"none\" stroke=\"blue\""
+        ));
+        assert!(svg.contains(
+            "<polyline class=\"waypoint\" points=\"10,-1 110,-11 110,-14 180,-42 174,-28 218,82\""
+        ));
+        assert!(svg.contains("<circle class=\"end\" cx=\"214\" cy=\"72\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
 
-        assert_eq!(20873, solution);
+        assert_eq!(
+            svg,
+            read_written("day_12.svg", |path| route.write_svg(path))
+        );
     }
 }
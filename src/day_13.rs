@@ -28,8 +28,7 @@ fn solve_part1(input: &Input) -> Output {
 
     let (departs, bus_id) = busses
         .iter()
-        .filter(|b| b.is_some())
-        .map(|b| b.unwrap())
+        .flatten()
         .map(|b| {
             if time % b == 0 {
                 (*time, b)
@@ -40,8 +40,8 @@ fn solve_part1(input: &Input) -> Output {
         }
     });
 
-    mem.iter()
-        .map(|(_, v)| {
+    mem.values()
+        .map(|v| {
             let v: String = v.iter().collect();
             usize::from_str_radix(&v, 2).unwrap()
         })
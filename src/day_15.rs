@@ -1,10 +1,199 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 const DAY: u32 = 15;
 
 type Input = Vec<u32>;
 type Output = u32;
 
+/// Last turn on which each number was spoken.
+pub trait Memory {
+    /// Records `number` as spoken on `turn`, returns the turn it was spoken before.
+    fn insert(&mut self, number: u32, turn: u32) -> Option<u32>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HashMemory(HashMap<u32, u32>);
+
+impl Memory for HashMemory {
+    fn insert(&mut self, number: u32, turn: u32) -> Option<u32> {
+        self.0.insert(number, turn)
+    }
+}
+
+/// Dense array of last seen turns, `0` marks a number never spoken. Numbers
+/// past the preallocated length are kept in a sparse map.
+#[derive(Debug, Clone)]
+pub struct HybridMemory {
+    dense: Vec<u32>,
+    sparse: HashMap<u32, u32>,
+}
+
+impl HybridMemory {
+    pub fn with_capacity(capacity: usize) -> Self {
+        HybridMemory {
+            dense: vec![0; capacity],
+            sparse: HashMap::new(),
+        }
+    }
+}
+
+impl Memory for HybridMemory {
+    fn insert(&mut self, number: u32, turn: u32) -> Option<u32> {
+        match self.dense.get_mut(number as usize) {
+            Some(last) => Some(std::mem::replace(last, turn)).filter(|&t| t != 0),
+            None => self.sparse.insert(number, turn),
+        }
+    }
+}
+
+/// Iterator over the numbers spoken in the memory game, starting with the
+/// starting numbers themselves.
+#[derive(Debug, Clone)]
+pub struct MemoryGame<M> {
+    memory: M,
+    start: Vec<u32>,
+    turn: u32,
+    next: u32,
+}
+
+impl<M: Memory> MemoryGame<M> {
+    pub fn with_memory(start: &[u32], memory: M) -> Self {
+        MemoryGame {
+            memory,
+            start: start.to_vec(),
+            turn: 1,
+            next: 0,
+        }
+    }
+}
+
+impl MemoryGame<HybridMemory> {
+    /// Game whose dense memory covers every number that can be spoken within
+    /// `turns` turns, since a spoken age is always smaller than the turn.
+    pub fn new(start: &[u32], turns: u32) -> Self {
+        Self::with_memory(start, HybridMemory::with_capacity(turns as usize))
+    }
+}
+
+impl<M: Memory> MemoryGame<M> {
+    /// Plays a single turn.
+    pub fn step(&mut self) -> Option<Turn> {
+        let spoken = match self.start.get(self.turn as usize - 1) {
+            Some(&n) => n,
+            None => self.next,
+        };
+
+        let turn = self.turn;
+        let previous = self.memory.insert(spoken, turn);
+        self.next = previous.map_or(0, |last| turn - last);
+        self.turn = turn.checked_add(1)?;
+
+        Some(Turn {
+            turn,
+            spoken,
+            previous,
+        })
+    }
+}
+
+impl<M: Memory> Iterator for MemoryGame<M> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step().map(|t| t.spoken)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Turn {
+    pub turn: u32,
+    pub spoken: u32,
+    /// Turn on which `spoken` was spoken before, if ever.
+    pub previous: Option<u32>,
+}
+
+impl Turn {
+    pub fn gap(&self) -> Option<u32> {
+        self.previous.map(|p| self.turn - p)
+    }
+}
+
+/// First turn, within `turns` turns, on which `number` is spoken.
+pub fn first_turn(start: &[u32], number: u32, turns: u32) -> Option<u32> {
+    let mut game = MemoryGame::new(start, turns);
+    std::iter::from_fn(|| game.step())
+        .take(turns as usize)
+        .find(|t| t.spoken == number)
+        .map(|t| t.turn)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameStats {
+    pub turns: u32,
+    /// How many times each number was spoken.
+    pub histogram: HashMap<u32, u32>,
+    /// Turn on which each number was first spoken.
+    pub first_spoken: HashMap<u32, u32>,
+    /// Turn with the longest gap between two utterances of the same number.
+    pub longest_gap: Option<Turn>,
+}
+
+impl GameStats {
+    pub fn collect(start: &[u32], turns: u32) -> Self {
+        let mut stats = GameStats {
+            turns: 0,
+            histogram: HashMap::new(),
+            first_spoken: HashMap::new(),
+            longest_gap: None,
+        };
+
+        let mut game = MemoryGame::new(start, turns);
+        for t in std::iter::from_fn(|| game.step()).take(turns as usize) {
+            stats.turns = t.turn;
+            *stats.histogram.entry(t.spoken).or_insert(0) += 1;
+            stats.first_spoken.entry(t.spoken).or_insert(t.turn);
+            if t.gap() > stats.longest_gap.and_then(|g| g.gap()) {
+                stats.longest_gap = Some(t);
+            }
+        }
+
+        stats
+    }
+}
+
+/// Looks for a game state, the number to be spoken next and the age of every
+/// number seen so far, that recurs within `turns` turns. Returns the turns of
+/// the two occurrences. Every state is kept, so this is meant for short games
+/// only.
+///
+/// A recurring state makes the game periodic from then on. The game is Van
+/// Eck's sequence for the start `[0]`, which never becomes periodic, but a
+/// start ending in two `1`s speaks `1` forever. As the ages of numbers never
+/// spoken again keep growing, a periodic tail is only found once every number
+/// seen is part of it.
+pub fn find_repeated_state(start: &[u32], turns: u32) -> Option<(u32, u32)> {
+    let mut last_seen = BTreeMap::new();
+    let mut states = HashMap::new();
+
+    let mut game = MemoryGame::new(start, turns);
+    for t in std::iter::from_fn(|| game.step()).take(turns as usize) {
+        last_seen.insert(t.spoken, t.turn);
+        // Until the starting numbers are spoken the next one is not decided
+        // by the game
+        if (t.turn as usize) < start.len() {
+            continue;
+        }
+
+        let next = t.gap().unwrap_or(0);
+        let ages: Vec<(u32, u32)> = last_seen.iter().map(|(&n, &l)| (n, t.turn - l)).collect();
+        if let Some(first) = states.insert((next, ages), t.turn) {
+            return Some((first, t.turn));
+        }
+    }
+
+    None
+}
+
 fn input_transformer(input: &str) -> Input {
     input
         .trim()
@@ -22,31 +211,20 @@ fn solve_part2(input: &Input) -> Output {
 }
 
 fn solve(input: &Input, target: u32) -> Output {
-    let start = (input.len() + 1) as u32;
-    let mut seen = input
-        .iter()
-        .enumerate()
-        .map(|(i, &e)| (e, (i as u32 + 1, i as u32 + 1)))
-        .collect::<HashMap<_, _>>();
-
-    (start..target + 1).fold(*input.last().unwrap(), |spoken, i| {
-        let spoken = seen
-            .get(&spoken)
-            .and_then(|(o1, o2)| if o1 != o2 { Some(o2 - o1) } else { None })
-            .unwrap_or(0);
-
-        let entry = seen.entry(spoken).or_insert((i, i));
-        entry.0 = entry.1;
-        entry.1 = i;
-
-        spoken
-    })
+    MemoryGame::new(input, target)
+        .nth(target as usize - 1)
+        .unwrap()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{
+        find_repeated_state, first_turn, input_transformer, solve_part1, solve_part2, GameStats,
+        HashMemory, HybridMemory, MemoryGame, DAY,
+    };
     use crate::utils::*;
+    use itertools::Itertools;
+    use test::Bencher;
 
     #[test]
     fn test_part1_sample() {
@@ -74,4 +252,74 @@ mod tests {
 
         assert_eq!(323780, solution);
     }
+
+    #[test]
+    fn test_spoken_numbers() {
+        let spoken = MemoryGame::new(&[0, 3, 6], 10).take(10).collect_vec();
+
+        assert_eq!(vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0], spoken);
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        let start = [5, 1, 9, 18, 13, 8, 1_000_000];
+        let hash = MemoryGame::with_memory(&start, HashMemory::default());
+        let hybrid = MemoryGame::with_memory(&start, HybridMemory::with_capacity(64));
+
+        assert!(hash.zip(hybrid).take(10_000).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn test_first_turn() {
+        assert_eq!(Some(9), first_turn(&[0, 3, 6], 4, 10));
+        assert_eq!(Some(2), first_turn(&[0, 3, 6], 3, 10));
+        assert_eq!(None, first_turn(&[0, 3, 6], 2, 10));
+    }
+
+    #[test]
+    fn test_game_stats() {
+        let stats = GameStats::collect(&[0, 3, 6], 10);
+
+        assert_eq!(10, stats.turns);
+        assert_eq!(Some(&4), stats.histogram.get(&0));
+        assert_eq!(Some(&3), stats.histogram.get(&3));
+        assert_eq!(Some(&7), stats.first_spoken.get(&1));
+        // 0 is spoken on turn 4 and then again on turn 8
+        let longest_gap = stats.longest_gap.unwrap();
+        assert_eq!((0, Some(4)), (longest_gap.spoken, longest_gap.previous));
+    }
+
+    #[test]
+    fn test_no_repeated_state() {
+        assert_eq!(None, find_repeated_state(&[0, 3, 6], 2020));
+        for start in [&[0][..], &[1], &[0, 0], &[2, 1, 2]].iter() {
+            assert_eq!(None, find_repeated_state(start, 500), "{:?}", start);
+        }
+    }
+
+    #[test]
+    fn test_repeated_state() {
+        // 1 was last spoken one turn ago, so 1 is spoken again, forever
+        assert_eq!(Some((2, 3)), find_repeated_state(&[1, 1], 10));
+        assert_eq!(Some((3, 4)), find_repeated_state(&[1, 1, 1], 10));
+        // 2 keeps aging, so the states differ although only 1s are spoken
+        assert_eq!(None, find_repeated_state(&[2, 1, 1], 100));
+    }
+
+    const BENCH_TURNS: u32 = 100_000;
+
+    #[bench]
+    fn bench_hash_memory(b: &mut Bencher) {
+        b.iter(|| {
+            MemoryGame::with_memory(&[5, 1, 9, 18, 13, 8, 0], HashMemory::default())
+                .nth(BENCH_TURNS as usize - 1)
+        });
+    }
+
+    #[bench]
+    fn bench_hybrid_memory(b: &mut Bencher) {
+        b.iter(|| {
+            MemoryGame::new(&[5, 1, 9, 18, 13, 8, 0], BENCH_TURNS).nth(BENCH_TURNS as usize - 1)
+        });
+    }
 }
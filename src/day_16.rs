@@ -1,137 +1,588 @@
+use crate::utils::*;
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
 
 const DAY: u32 = 16;
 
+/// Set of values given as sorted, disjoint and non-adjacent inclusive ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RangeSet(Vec<(usize, usize)>);
+
+impl RangeSet {
+    /// The ranges in increasing order.
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.0
+    }
+
+    /// Adds the inclusive range `lo..=hi`, merging it with the ranges it touches.
+    pub fn insert(&mut self, lo: usize, hi: usize) {
+        if lo > hi {
+            return;
+        }
+        // Ranges touching or overlapping [lo, hi] are merged into it
+        let first = self.0.partition_point(|&(_, h)| h.saturating_add(1) < lo);
+        let last = self.0.partition_point(|&(l, _)| l <= hi.saturating_add(1));
+        let merged = self.0[first..last]
+            .iter()
+            .fold((lo, hi), |(lo, hi), &(l, h)| (lo.min(l), hi.max(h)));
+        self.0.splice(first..last, std::iter::once(merged));
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        let i = self.0.partition_point(|&(_, h)| h < value);
+        self.0.get(i).is_some_and(|&(l, _)| l <= value)
+    }
+
+    pub fn union(&self, other: &RangeSet) -> RangeSet {
+        self.0.iter().chain(other.0.iter()).copied().collect()
+    }
+
+    pub fn intersection(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < self.0.len() && j < other.0.len() {
+            let (a, b) = (self.0[i], other.0[j]);
+            let (lo, hi) = (a.0.max(b.0), a.1.min(b.1));
+            if lo <= hi {
+                ranges.push((lo, hi));
+            }
+            if a.1 < b.1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        RangeSet(ranges)
+    }
+}
+
+impl FromIterator<(usize, usize)> for RangeSet {
+    fn from_iter<I: IntoIterator<Item = (usize, usize)>>(iter: I) -> Self {
+        let mut set = RangeSet::default();
+        iter.into_iter().for_each(|(lo, hi)| set.insert(lo, hi));
+        set
+    }
+}
+
+impl fmt::Display for RangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = self.0.iter().map(|(lo, hi)| match lo == hi {
+            true => lo.to_string(),
+            false => format!("{}-{}", lo, hi),
+        });
+        write!(f, "{}", ranges.format(" or "))
+    }
+}
+
+impl FromStr for RangeSet {
+    type Err = Error;
+
+    /// Parses ranges like `1-3 or 5-7 or 9`.
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(" or ")
+            .map(|r| {
+                let (lo, hi) = r.trim().split_once('-').unwrap_or((r, r));
+                Ok((lo.trim().parse()?, hi.trim().parse()?))
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Prop {
     key: String,
-    value: (usize, usize, usize, usize),
+    value: RangeSet,
 }
 
 impl Prop {
     fn is_valid(&self, value: usize) -> bool {
-        (self.value.0 <= value && value <= self.value.1)
-            || (self.value.2 <= value && value <= self.value.3)
+        self.value.contains(value)
     }
 }
 
+/// Static interval lookup over the ranges of all fields. The value axis is cut
+/// into elementary segments at every range boundary, each segment knows the
+/// fields covering it, so a lookup is a binary search over the segments.
 #[derive(Debug, Clone)]
-struct Notes {
+struct FieldIndex {
+    keys: Vec<String>,
+    starts: Vec<usize>,
+    fields: Vec<Vec<usize>>,
+}
+
+impl FieldIndex {
+    fn new(props: &HashMap<String, Prop>) -> Self {
+        let props: Vec<&Prop> = props.values().sorted_by_key(|p| &p.key).collect();
+        let starts: Vec<usize> = props
+            .iter()
+            .flat_map(|p| p.value.ranges())
+            .flat_map(|&(lo, hi)| vec![lo, hi.saturating_add(1)])
+            .sorted()
+            .dedup()
+            .collect();
+        let fields = starts
+            .iter()
+            .map(|&s| (0..props.len()).filter(|&i| props[i].is_valid(s)).collect())
+            .collect();
+
+        FieldIndex {
+            keys: props.iter().map(|p| p.key.to_owned()).collect(),
+            starts,
+            fields,
+        }
+    }
+
+    /// Indices into `keys` of the fields whose ranges contain `value`.
+    fn fields_containing(&self, value: usize) -> &[usize] {
+        match self.starts.partition_point(|&s| s <= value) {
+            0 => &[],
+            i => &self.fields[i - 1],
+        }
+    }
+
+    fn is_valid(&self, value: usize) -> bool {
+        !self.fields_containing(value).is_empty()
+    }
+}
+
+/// The puzzle notes, parsed with `FromStr`.
+#[derive(Debug, Clone)]
+pub struct Notes {
     props: HashMap<String, Prop>,
     your_ticket: Vec<usize>,
     nearby_ticket: Vec<Vec<usize>>,
 }
 
+impl Notes {
+    pub fn your_ticket(&self) -> &[usize] {
+        &self.your_ticket
+    }
+
+    pub fn nearby_tickets(&self) -> &[Vec<usize>] {
+        &self.nearby_ticket
+    }
+
+    /// The field rules in key order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &RangeSet)> {
+        self.props
+            .values()
+            .sorted_by_key(|p| &p.key)
+            .map(|p| (p.key.as_str(), &p.value))
+    }
+}
+
 type Input = Notes;
 type Output = usize;
 
-fn input_transformer(input: &str) -> Input {
-    let mut notes = Input {
-        props: HashMap::new(),
-        your_ticket: Vec::new(),
-        nearby_ticket: Vec::new(),
-    };
+impl FromStr for Notes {
+    type Err = Error;
 
-    let mut stage = 0;
-    input
-        .lines()
-        .map(|l| l.trim())
-        .filter(|l| !l.is_empty())
-        .for_each(|l| {
-            if l == "your ticket:" || l == "nearby tickets:" {
-                stage += 1;
-            } else {
-                match stage {
-                    0 => {
-                        let (key, value) = l.split(": ").collect_tuple().unwrap();
-                        let value = value
-                            .split(" or ")
-                            .flat_map(|v| v.split('-'))
-                            .map(|v| v.parse().unwrap())
-                            .collect_tuple()
-                            .unwrap();
-
-                        notes.props.insert(
-                            key.to_owned(),
-                            Prop {
-                                key: key.to_owned(),
-                                value,
-                            },
-                        );
-                    }
-                    1 => {
-                        notes.your_ticket = l.split(',').map(|x| x.parse().unwrap()).collect();
-                    }
-                    2 => {
-                        notes
-                            .nearby_ticket
-                            .push(l.split(',').map(|x| x.parse().unwrap()).collect());
-                    }
-                    _ => panic!("Invalid stage!"),
-                };
+    /// Field rules first, then a "your ticket:" and a "nearby tickets:"
+    /// section, each a paragraph starting with its title.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut notes = Notes {
+            props: HashMap::new(),
+            your_ticket: Vec::new(),
+            nearby_ticket: Vec::new(),
+        };
+
+        let mut sections = paragraphs(s);
+        let rules = sections.next().ok_or("expected field rules")?;
+        for (line, l) in rules.lines() {
+            let (key, value) = l
+                .split_once(": ")
+                .ok_or_else(|| format!("line {}: expected '<field>: <ranges>'", line))?;
+            let value = value.parse().map_err(|e| format!("line {}: {}", line, e))?;
+
+            notes.props.insert(
+                key.to_owned(),
+                Prop {
+                    key: key.to_owned(),
+                    value,
+                },
+            );
+        }
+
+        let (mut your_ticket, mut nearby_tickets) = (false, false);
+        let mut width = None;
+        for section in sections {
+            let mut lines = section.lines();
+            let (line, title) = lines.next().unwrap();
+            match title {
+                "your ticket:" if !your_ticket => {
+                    your_ticket = true;
+                    let ticket = lines
+                        .exactly_one()
+                        .map_err(|_| format!("line {}: expected one ticket", line))?;
+                    notes.your_ticket = parse_ticket(ticket, &mut width)?;
+                }
+                "nearby tickets:" if !nearby_tickets => {
+                    nearby_tickets = true;
+                    notes.nearby_ticket = lines
+                        .map(|l| parse_ticket(l, &mut width))
+                        .collect::<Result<_>>()?;
+                }
+                _ => return Err(format!("line {}: unexpected section '{}'", line, title).into()),
             }
-        });
+        }
+
+        match (your_ticket, nearby_tickets) {
+            (false, _) => Err("missing the 'your ticket:' section".into()),
+            (_, false) => Err("missing the 'nearby tickets:' section".into()),
+            _ => Ok(notes),
+        }
+    }
+}
 
-    notes
+/// Parses a ticket, which must have `width` values once the first is parsed.
+fn parse_ticket((line, l): (usize, &str), width: &mut Option<usize>) -> Result<Vec<usize>> {
+    let ticket: Vec<usize> = l
+        .split(',')
+        .map(|x| x.parse())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|e| format!("line {}: {}", line, e))?;
+
+    match *width.get_or_insert(ticket.len()) {
+        w if w != ticket.len() => {
+            Err(format!("line {}: expected {} values, got {}", line, w, ticket.len()).into())
+        }
+        _ => Ok(ticket),
+    }
+}
+
+fn input_transformer(input: &str) -> Input {
+    input.parse().unwrap()
 }
 
 fn solve_part1(input: &Input) -> Output {
+    let index = FieldIndex::new(&input.props);
     input
         .nearby_ticket
         .iter()
-        .flat_map(|t| {
-            t.iter()
-                .filter(|v| !input.props.values().any(|p| p.is_valid(**v)))
-        })
+        .flat_map(|t| t.iter().filter(|v| !index.is_valid(**v)))
         .sum()
 }
 
-fn solve_part2(input: &Input) -> Output {
-    let valid_tickets: Vec<Vec<usize>> = input
+/// Why fields could not be assigned to columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssignmentError {
+    /// No perfect matching exists, lists fields which fit no column at all.
+    Impossible(Vec<String>),
+    /// Several matchings exist, lists them up to the requested limit.
+    Ambiguous(Vec<HashMap<String, usize>>),
+}
+
+impl fmt::Display for AssignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssignmentError::Impossible(fields) if fields.is_empty() => {
+                write!(f, "fields can not be assigned to distinct columns")
+            }
+            AssignmentError::Impossible(fields) => {
+                write!(f, "no column fits fields {}", fields.join(", "))
+            }
+            AssignmentError::Ambiguous(assignments) if assignments.len() < 2 => {
+                write!(f, "fields can be assigned to columns in several ways")
+            }
+            AssignmentError::Ambiguous(assignments) => write!(
+                f,
+                "fields can be assigned to columns in at least {} ways",
+                assignments.len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssignmentError {}
+
+fn valid_tickets(input: &Input) -> Vec<&Vec<usize>> {
+    let index = FieldIndex::new(&input.props);
+    input
         .nearby_ticket
         .iter()
-        .filter(|t| {
-            t.iter()
-                .all(|v| input.props.values().any(|p| p.is_valid(*v)))
+        .filter(|t| t.iter().all(|v| index.is_valid(*v)))
+        .collect()
+}
+
+/// Columns each field is compatible with, fields in key order.
+fn compatibility(input: &Input) -> Vec<(&Prop, Vec<usize>)> {
+    let valid_tickets = valid_tickets(input);
+
+    input
+        .props
+        .values()
+        .sorted_by_key(|p| &p.key)
+        .map(|p| {
+            let columns = (0..input.your_ticket.len())
+                .filter(|&i| valid_tickets.iter().all(|t| p.is_valid(t[i])))
+                .collect();
+            (p, columns)
         })
-        .cloned()
+        .collect()
+}
+
+/// Backtracking search for perfect matchings of fields to columns, trying the
+/// most constrained fields first. Stops after `limit` matchings are found.
+fn matchings(candidates: &[Vec<usize>], limit: usize) -> Vec<Vec<usize>> {
+    fn search(
+        candidates: &[Vec<usize>],
+        order: &[usize],
+        used: &mut Vec<bool>,
+        current: &mut Vec<usize>,
+        found: &mut Vec<Vec<usize>>,
+        limit: usize,
+    ) {
+        if found.len() >= limit {
+            return;
+        }
+        let field = match order.first() {
+            Some(&field) => field,
+            None => {
+                found.push(current.clone());
+                return;
+            }
+        };
+
+        for &column in &candidates[field] {
+            if !used[column] {
+                used[column] = true;
+                current[field] = column;
+                search(candidates, &order[1..], used, current, found, limit);
+                used[column] = false;
+            }
+        }
+    }
+
+    let order: Vec<usize> = (0..candidates.len())
+        .sorted_by_key(|&i| candidates[i].len())
         .collect();
+    let columns = candidates.iter().flatten().max().map_or(0, |c| c + 1);
+
+    let mut found = vec![];
+    search(
+        candidates,
+        &order,
+        &mut vec![false; columns],
+        &mut vec![0; candidates.len()],
+        &mut found,
+        limit,
+    );
+
+    found
+}
+
+/// Assigns every field to a distinct column, failing unless exactly one
+/// assignment exists. When ambiguous, `AssignmentError::Ambiguous` lists at
+/// most `limit` of the assignments, so pass `usize::MAX` to list them all.
+/// Ambiguity is detected whatever the limit, even `0`.
+pub fn assign_fields(
+    input: &Input,
+    limit: usize,
+) -> std::result::Result<HashMap<String, usize>, AssignmentError> {
+    let compatibility = compatibility(input);
+    let candidates: Vec<Vec<usize>> = compatibility.iter().map(|(_, c)| c.clone()).collect();
+
+    let mut assignments: Vec<HashMap<String, usize>> = matchings(&candidates, limit.max(2))
+        .into_iter()
+        .map(|m| {
+            compatibility
+                .iter()
+                .zip(m)
+                .map(|((p, _), column)| (p.key.to_owned(), column))
+                .collect()
+        })
+        .collect();
+
+    match assignments.len() {
+        0 => Err(AssignmentError::Impossible(
+            compatibility
+                .iter()
+                .filter(|(_, c)| c.is_empty())
+                .map(|(p, _)| p.key.to_owned())
+                .collect(),
+        )),
+        1 => Ok(assignments.remove(0)),
+        _ => {
+            assignments.truncate(limit);
+            Err(AssignmentError::Ambiguous(assignments))
+        }
+    }
+}
+
+fn solve_part2(input: &Input) -> Result<Output> {
+    let props_map = assign_fields(input, 1)?;
+
+    Ok(props_map
+        .iter()
+        .filter(|(k, _)| k.starts_with("departure"))
+        .map(|(_, &i)| input.your_ticket[i])
+        .product())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InvalidValue {
+    pub column: usize,
+    pub value: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TicketReport {
+    /// Position of the ticket among the nearby tickets.
+    pub ticket: usize,
+    pub values: Vec<usize>,
+    /// Fields whose ranges contain each value.
+    pub fields: Vec<Vec<String>>,
+    pub invalid: Vec<InvalidValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnReport {
+    pub column: usize,
+    /// Fields matching the column on every valid ticket.
+    pub candidates: Vec<String>,
+}
 
-    let all_prop_keys: HashSet<String> = input.props.keys().cloned().collect();
-    let mut is: HashSet<usize> = (0..input.your_ticket.len()).collect();
-    let mut props_map = HashMap::<String, usize>::with_capacity(input.your_ticket.len());
-    let mut i = 0;
-    while input.your_ticket.len() != props_map.len() && i < input.your_ticket.len() {
-        all_prop_keys
-            .difference(&props_map.keys().cloned().collect())
-            .map(|p| input.props.get(p).unwrap())
-            .for_each(|p| {
-                let possible = is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldSummary {
+    pub field: String,
+    pub ranges: String,
+    /// Nearby ticket values, valid tickets only, within the field ranges.
+    pub matching_values: usize,
+    pub total_values: usize,
+    pub columns: Vec<usize>,
+}
+
+/// Validation report of the notes, for inspecting inputs which do not solve.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    pub tickets: Vec<TicketReport>,
+    pub columns: Vec<ColumnReport>,
+    pub fields: Vec<FieldSummary>,
+}
+
+impl ValidationReport {
+    pub fn new(input: &Notes) -> Self {
+        let index = FieldIndex::new(&input.props);
+        let tickets = input
+            .nearby_ticket
+            .iter()
+            .enumerate()
+            .map(|(ticket, values)| TicketReport {
+                ticket,
+                values: values.clone(),
+                fields: values
+                    .iter()
+                    .map(|&v| {
+                        let fields = index.fields_containing(v).iter();
+                        fields.map(|&i| index.keys[i].to_owned()).collect()
+                    })
+                    .collect(),
+                invalid: values
                     .iter()
-                    .filter(|i| valid_tickets.iter().map(|t| t[**i]).all(|x| p.is_valid(x)))
-                    .copied()
-                    .exactly_one();
+                    .enumerate()
+                    .filter(|(_, v)| !index.is_valid(**v))
+                    .map(|(column, &value)| InvalidValue {
+                        column,
+                        value,
+                        reason: invalid_reason(input, value),
+                    })
+                    .collect(),
+            })
+            .collect();
 
-                if let Ok(index) = possible {
-                    props_map.insert(p.key.to_owned(), index);
-                    is.remove(&index);
-                }
-            });
+        let compatibility = compatibility(input);
+        let columns = (0..input.your_ticket.len())
+            .map(|column| ColumnReport {
+                column,
+                candidates: compatibility
+                    .iter()
+                    .filter(|(_, c)| c.contains(&column))
+                    .map(|(p, _)| p.key.to_owned())
+                    .collect(),
+            })
+            .collect();
+
+        let valid_values: Vec<usize> = valid_tickets(input)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect();
+        let fields = compatibility
+            .into_iter()
+            .map(|(p, columns)| FieldSummary {
+                field: p.key.to_owned(),
+                ranges: p.value.to_string(),
+                matching_values: valid_values.iter().filter(|v| p.is_valid(**v)).count(),
+                total_values: valid_values.len(),
+                columns,
+            })
+            .collect();
+
+        ValidationReport {
+            tickets,
+            columns,
+            fields,
+        }
+    }
 
-        i += 1;
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
     }
 
-    props_map
-        .keys()
-        .filter(|k| k.starts_with("departure"))
-        .map(|k| input.your_ticket[*props_map.get(k).unwrap()])
-        .product()
+    /// One row per nearby ticket value with the fields it fits.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("ticket,column,value,valid,fields,reason\n");
+        for t in &self.tickets {
+            for (column, value) in t.values.iter().enumerate() {
+                let invalid = t.invalid.iter().find(|i| i.column == column);
+                let fields = t.fields[column].join(";");
+                csv.push_str(&format!(
+                    "{},{},{},{},\"{}\",\"{}\"\n",
+                    t.ticket,
+                    column,
+                    value,
+                    invalid.is_none(),
+                    fields.replace('"', "\"\""),
+                    invalid.map_or(String::new(), |i| i.reason.replace('"', "\"\""))
+                ));
+            }
+        }
+
+        csv
+    }
+}
+
+fn invalid_reason(input: &Input, value: usize) -> String {
+    let nearest = input
+        .props
+        .values()
+        .flat_map(|p| p.value.ranges().iter().map(move |r| (p, r)))
+        .min_by_key(|(p, &(lo, hi))| {
+            let distance = if value < lo { lo - value } else { value - hi };
+            (distance, &p.key)
+        });
+
+    match nearest {
+        Some((p, &(lo, hi))) => format!(
+            "{} is outside every field range, nearest is {} {}-{}",
+            value, p.key, lo, hi
+        ),
+        None => format!("{} is outside every field range", value),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{
+        assign_fields, input_transformer, solve_part1, solve_part2, AssignmentError, FieldIndex,
+        Notes, RangeSet, ValidationReport, DAY,
+    };
     use crate::utils::*;
 
     #[test]
@@ -174,7 +625,7 @@ mod tests {
         3,9,18
         15,1,5
         5,14,9";
-        let solution = solve_part2(&input_transformer(input));
+        let solution = solve_part2(&input_transformer(input)).unwrap();
 
         assert_eq!(1, solution);
     }
@@ -182,8 +633,190 @@ mod tests {
     #[test]
     fn test_part2_puzzle() {
         let input = read_input(2020, DAY).unwrap();
-        let solution = solve_part2(&input_transformer(&input));
+        let solution = solve_part2(&input_transformer(&input)).unwrap();
 
         assert_eq!(2564529489989, solution);
     }
+
+    #[test]
+    fn test_assign_fields_ambiguous() {
+        let input = "class: 0-1 or 4-19
+        row: 0-5 or 8-19
+        
+        your ticket:
+        11,12
+        
+        nearby tickets:
+        5,5
+        15,1";
+        let input = input_transformer(input);
+
+        for &(limit, listed) in &[(10, 2), (1, 1), (0, 0)] {
+            match assign_fields(&input, limit).unwrap_err() {
+                AssignmentError::Ambiguous(assignments) => assert_eq!(listed, assignments.len()),
+                error => panic!("Expected ambiguous assignment, got {:?}!", error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_assign_fields_impossible() {
+        let input = "class: 0-1 or 4-19
+        row: 0-5 or 8-19
+        seat: 0-3 or 16-19
+        
+        your ticket:
+        11,12,13
+        
+        nearby tickets:
+        3,9,18
+        15,1,5
+        5,14,9";
+        let error = assign_fields(&input_transformer(input), 10).unwrap_err();
+
+        assert_eq!(AssignmentError::Impossible(vec!["seat".to_string()]), error);
+        assert_eq!("no column fits fields seat", error.to_string());
+    }
+
+    #[test]
+    fn test_range_set() {
+        let a: RangeSet = "5-7 or 1-3 or 4 or 10-12".parse().unwrap();
+        assert_eq!(&[(1, 7), (10, 12)], a.ranges());
+        assert!(a.contains(4) && a.contains(12));
+        assert!(!a.contains(0) && !a.contains(8) && !a.contains(13));
+
+        let b: RangeSet = "6-11".parse().unwrap();
+        assert_eq!(&[(1, 12)], a.union(&b).ranges());
+        assert_eq!(&[(6, 7), (10, 11)], a.intersection(&b).ranges());
+
+        assert!("1-x".parse::<RangeSet>().is_err());
+    }
+
+    #[test]
+    fn test_field_index() {
+        let input = "class: 1-3 or 5-7 or 30-31
+        row: 6-11
+        seat: 13-40 or 45-50
+        
+        your ticket:
+        7,1,14
+        
+        nearby tickets:
+        7,3,47";
+        let notes = input_transformer(input);
+        let index = FieldIndex::new(&notes.props);
+        let keys = |v| {
+            index
+                .fields_containing(v)
+                .iter()
+                .map(|&i| index.keys[i].as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["class", "row"], keys(6));
+        assert_eq!(vec!["class", "seat"], keys(30));
+        assert_eq!(Vec::<&str>::new(), keys(12));
+        assert_eq!(Vec::<&str>::new(), keys(51));
+        assert_eq!(Vec::<&str>::new(), keys(0));
+    }
+
+    #[test]
+    fn test_validation_report() {
+        let input = "class: 1-3 or 5-7
+        row: 6-11 or 33-44
+        seat: 13-40 or 45-50
+        
+        your ticket:
+        7,1,14
+        
+        nearby tickets:
+        7,3,47
+        40,4,50
+        55,2,20
+        38,6,12";
+        let report = ValidationReport::new(&input_transformer(input));
+
+        let invalid: Vec<(usize, usize, usize)> = report
+            .tickets
+            .iter()
+            .flat_map(|t| t.invalid.iter().map(move |i| (t.ticket, i.column, i.value)))
+            .collect();
+        assert_eq!(vec![(1, 1, 4), (2, 0, 55), (3, 2, 12)], invalid);
+        assert_eq!(
+            "4 is outside every field range, nearest is class 1-3",
+            report.tickets[1].invalid[0].reason
+        );
+        assert_eq!(vec!["class", "row"], report.columns[0].candidates);
+        assert_eq!("class", report.fields[0].field);
+        assert_eq!("1-3 or 5-7", report.fields[0].ranges);
+        assert_eq!(2, report.fields[0].matching_values);
+        assert_eq!(3, report.fields[0].total_values);
+
+        let csv = report.to_csv();
+        assert_eq!(13, csv.lines().count());
+        assert_eq!(
+            "1,1,4,false,\"\",\"4 is outside every field range, nearest is class 1-3\"",
+            csv.lines().nth(5).unwrap()
+        );
+        assert_eq!("0,0,7,true,\"class;row\",\"\"", csv.lines().nth(1).unwrap());
+
+        let quoted = "\"class\" a: 1-3\n\nyour ticket:\n1\n\nnearby tickets:\n9";
+        let csv = ValidationReport::new(&quoted.parse().unwrap()).to_csv();
+        assert_eq!(
+            "0,0,9,false,\"\",\"9 is outside every field range, nearest is \"\"class\"\" a 1-3\"",
+            csv.lines().nth(1).unwrap()
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(55, json["tickets"][2]["invalid"][0]["value"]);
+    }
+
+    #[test]
+    fn test_parse_sections() {
+        let rules = "class: 1-3 or 5-7\n\n";
+        let notes: Notes = format!("{}nearby tickets:\n7,3\n40,4\n\nyour ticket:\n7,1", rules)
+            .parse()
+            .unwrap();
+        assert_eq!(&[7, 1], notes.your_ticket());
+        assert_eq!(&[vec![7, 3], vec![40, 4]], notes.nearby_tickets());
+        assert_eq!(
+            vec!["class: 1-3 or 5-7".to_string()],
+            notes
+                .fields()
+                .map(|(key, ranges)| format!("{}: {}", key, ranges))
+                .collect::<Vec<_>>()
+        );
+
+        let error = |input: String| input.parse::<Notes>().unwrap_err().to_string();
+        assert_eq!(
+            "line 9: unexpected section '40,4'",
+            error(format!(
+                "{}your ticket:\n7,1\n\nnearby tickets:\n7,3\n\n40,4",
+                rules
+            ))
+        );
+        assert_eq!(
+            "missing the 'nearby tickets:' section",
+            error(format!("{}your ticket:\n7,1", rules))
+        );
+        assert_eq!(
+            "line 3: expected one ticket",
+            error(format!(
+                "{}your ticket:\n7,1\n1,7\n\nnearby tickets:",
+                rules
+            ))
+        );
+        assert_eq!(
+            "line 4: invalid digit found in string",
+            error(format!("{}your ticket:\n7,x\n\nnearby tickets:", rules))
+        );
+        assert_eq!(
+            "line 7: expected 2 values, got 1",
+            error(format!("{}your ticket:\n7,1\n\nnearby tickets:\n7", rules))
+        );
+        assert_eq!(
+            "line 7: expected 2 values, got 1",
+            error(format!("{}nearby tickets:\n7,3\n\nyour ticket:\n7", rules))
+        );
+    }
 }
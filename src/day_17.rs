@@ -1,47 +1,334 @@
-use std::collections::HashSet;
+use crate::utils::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
 use itertools::Itertools;
 
 const DAY: u32 = 17;
 
-#[derive(Debug, Hash, Clone, Eq, PartialEq)]
-struct Coordinate(isize, isize, isize, isize);
+#[derive(Debug, Hash, Clone, Copy, Eq, PartialEq)]
+struct Coordinate<const D: usize>([isize; D]);
 
-enum Dimension {
-    Three,
-    Four,
+/// Offsets to all `3^D - 1` neighbours, the digits of `0..3^D` in base 3
+/// shifted to `-1..=1`.
+fn neighbor_deltas<const D: usize>() -> Vec<[isize; D]> {
+    (0..3usize.pow(D as u32))
+        .map(|mut n| {
+            let mut delta = [0; D];
+            for d in delta.iter_mut() {
+                *d = (n % 3) as isize - 1;
+                n /= 3;
+            }
+            delta
+        })
+        .filter(|delta| delta.iter().any(|&d| d != 0))
+        .collect()
 }
 
-lazy_static! {
-    static ref NEIGHBOR_DELTAS: Vec<(isize, isize, isize, isize)> =
-        [-1, -1, -1, -1, 0, 0, 0, 0, 1, 1, 1, 1]
+impl<const D: usize> Coordinate<D> {
+    /// Places a point of the 2D starting slice at the origin of the other axes.
+    fn from_slice(x: isize, y: isize) -> Self {
+        assert!(D >= 2, "Conway Cubes need at least 2 dimensions!");
+        let mut c = [0; D];
+        c[0] = x;
+        c[1] = y;
+        Coordinate(c)
+    }
+
+    fn neighbors<'a>(&'a self, deltas: &'a [[isize; D]]) -> impl Iterator<Item = Self> + 'a {
+        deltas.iter().map(move |delta| {
+            let mut c = self.0;
+            c.iter_mut().zip(delta).for_each(|(c, d)| *c += d);
+            Coordinate(c)
+        })
+    }
+
+    /// The starting slice lies flat in every axis past the first two, so the
+    /// space stays symmetric under mirroring and swapping those axes. The
+    /// representative of a cell has them non-negative and sorted.
+    fn canonical(mut self) -> Self {
+        let extra = &mut self.0[2..];
+        extra.iter_mut().for_each(|c| *c = c.abs());
+        extra.sort_unstable();
+        self
+    }
+
+    /// Number of distinct cells a canonical cell stands for.
+    fn multiplicity(&self) -> usize {
+        let extra = &self.0[2..];
+        let permutations = extra
             .iter()
-            .permutations(4)
-            .unique()
-            .map(|p| p
-                .into_iter()
-                .copied()
-                .collect_tuple::<(isize, isize, isize, isize)>()
-                .unwrap())
-            .filter(|p| *p != (0, 0, 0, 0))
-            .collect();
+            .group_by(|c| **c)
+            .into_iter()
+            .fold(factorial(extra.len()), |acc, (_, g)| {
+                acc / factorial(g.count())
+            });
+        let mirrors = 1 << extra.iter().filter(|c| **c != 0).count();
+
+        permutations * mirrors
+    }
+
+    /// All cells a canonical cell stands for.
+    fn images(&self) -> HashSet<Self> {
+        let extra = &self.0[2..];
+        extra
+            .iter()
+            .permutations(extra.len())
+            .cartesian_product(0..1 << extra.len())
+            .map(|(permutation, signs)| {
+                let mut c = self.0;
+                for (i, v) in permutation.into_iter().enumerate() {
+                    c[i + 2] = if signs & (1 << i) == 0 { *v } else { -*v };
+                }
+                Coordinate(c)
+            })
+            .collect()
+    }
 }
 
-impl Coordinate {
-    fn neighbors<'a>(&'a self, dim: &'a Dimension) -> impl Iterator<Item = Coordinate> + '_ {
-        NEIGHBOR_DELTAS
+/// Life-like rule, the neighbour counts on which a cell is born or survives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub born: HashSet<usize>,
+    pub survive: HashSet<usize>,
+}
+
+impl Rule {
+    pub fn conway() -> Self {
+        "B3/S23".parse().unwrap()
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    /// Parses `B3/S23`, counts above 9 are given comma separated as `B3/S2,3,12`.
+    fn from_str(s: &str) -> Result<Self> {
+        fn counts(s: &str, prefix: char) -> Result<HashSet<usize>> {
+            let s = s
+                .trim()
+                .strip_prefix(|c: char| c.to_ascii_uppercase() == prefix)
+                .ok_or_else(|| format!("Missing '{}' in rule!", prefix))?;
+            if s.contains(',') {
+                Ok(s.split(',')
+                    .map(|n| n.trim().parse())
+                    .collect::<std::result::Result<_, _>>()?)
+            } else {
+                s.chars()
+                    .map(|c| {
+                        c.to_digit(10)
+                            .map(|d| d as usize)
+                            .ok_or_else(|| format!("Invalid count '{}' in rule!", c).into())
+                    })
+                    .collect()
+            }
+        }
+
+        let (born, survive) = s.split_once('/').ok_or("Rule must look like 'B3/S23'!")?;
+        let rule = Rule {
+            born: counts(born, 'B')?,
+            survive: counts(survive, 'S')?,
+        };
+        if rule.born.contains(&0) {
+            return Err("Rules with B0 would fill the infinite space!".into());
+        }
+
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = |set: &HashSet<usize>| {
+            let separator = if set.iter().any(|&n| n > 9) { "," } else { "" };
+            set.iter().sorted().join(separator)
+        };
+        write!(f, "B{}/S{}", counts(&self.born), counts(&self.survive))
+    }
+}
+
+/// Bounding box and population of the active cells after `cycle` cycles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generation<const D: usize> {
+    pub cycle: u32,
+    pub population: usize,
+    /// Inclusive minimum and maximum coordinate on each axis.
+    pub bounds: Option<([isize; D], [isize; D])>,
+}
+
+fn factorial(n: usize) -> usize {
+    (1..=n).product()
+}
+
+/// Pocket dimension keeping canonical active cells only.
+struct Space<const D: usize> {
+    rule: Rule,
+    deltas: Vec<[isize; D]>,
+    active: HashSet<Coordinate<D>>,
+    cycle: u32,
+}
+
+impl<const D: usize> Space<D> {
+    fn new(input: &[(isize, isize)], rule: Rule) -> Self {
+        Space {
+            rule,
+            deltas: neighbor_deltas(),
+            active: input
+                .iter()
+                .map(|&(x, y)| Coordinate::from_slice(x, y))
+                .collect(),
+            cycle: 0,
+        }
+    }
+
+    fn cells(&self) -> HashSet<Coordinate<D>> {
+        self.active.iter().flat_map(|c| c.images()).collect()
+    }
+
+    fn generation(&self) -> Generation<D> {
+        let cells = self.cells();
+        let bounds = cells.iter().fold(None, |bounds, c| {
+            let (mut lo, mut hi) = bounds.unwrap_or((c.0, c.0));
+            for i in 0..D {
+                lo[i] = lo[i].min(c.0[i]);
+                hi[i] = hi[i].max(c.0[i]);
+            }
+            Some((lo, hi))
+        });
+
+        Generation {
+            cycle: self.cycle,
+            population: cells.len(),
+            bounds,
+        }
+    }
+
+    /// Slices of the space in the puzzle text format, one for each combination
+    /// of the coordinates past `x` and `y`, cropped to the active cells.
+    fn render(&self) -> String {
+        const AXES: [&str; 2] = ["z", "w"];
+
+        let cells = self.cells();
+        let (lo, hi) = match self.generation().bounds {
+            Some(bounds) => bounds,
+            None => return String::new(),
+        };
+
+        cells
             .iter()
-            .filter(move |d| match dim {
-                Dimension::Three => d.3 == 0,
-                _ => true,
+            .map(|c| c.0[2..].to_vec())
+            .unique()
+            .sorted_by(|a, b| a.iter().rev().cmp(b.iter().rev()))
+            .map(|slice| {
+                let header = slice
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| match AXES.get(i) {
+                        Some(axis) => format!("{}={}", axis, v),
+                        None => format!("d{}={}", i + 2, v),
+                    })
+                    .join(", ");
+                let rows = (lo[1]..=hi[1]).map(|y| {
+                    (lo[0]..=hi[0])
+                        .map(|x| {
+                            let mut c = [0; D];
+                            c[0] = x;
+                            c[1] = y;
+                            c[2..].copy_from_slice(&slice);
+                            if cells.contains(&Coordinate(c)) {
+                                '#'
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect::<String>()
+                });
+
+                std::iter::once(header)
+                    .filter(|h| !h.is_empty())
+                    .chain(rows)
+                    .join("\n")
             })
-            .map(move |(dx, dy, dz, dw)| {
-                Coordinate(self.0 + dx, self.1 + dy, self.2 + dz, self.3 + dw)
+            .join("\n\n")
+    }
+
+    fn population(&self) -> usize {
+        self.active.iter().map(|c| c.multiplicity()).sum()
+    }
+
+    fn step(&mut self) {
+        // Every cell of the orbit of `c` adds to its neighbours, so summing
+        // over canonical neighbours counts each target `multiplicity` times
+        let mut counts: HashMap<Coordinate<D>, usize> = HashMap::new();
+        for c in &self.active {
+            let weight = c.multiplicity();
+            for n in c.neighbors(&self.deltas) {
+                *counts.entry(n.canonical()).or_insert(0) += weight;
+            }
+        }
+
+        // Cells without active neighbours are missing from the counts
+        let lonely = self
+            .active
+            .iter()
+            .filter(|c| !counts.contains_key(c))
+            .map(|c| (*c, 0))
+            .collect_vec();
+
+        let rule = &self.rule;
+        let active = &self.active;
+        self.active = counts
+            .into_iter()
+            .map(|(c, count)| (c, count / c.multiplicity()))
+            .chain(lonely)
+            .filter(|(c, count)| match active.contains(c) {
+                true => rule.survive.contains(count),
+                false => rule.born.contains(count),
             })
+            .map(|(c, _)| c)
+            .collect();
+        self.cycle += 1;
     }
 }
 
-type Input = HashSet<Coordinate>;
+/// Runs the simulation from the active `(x, y)` cells of the initial slice
+/// and returns the statistics of every generation, starting with the
+/// initial one.
+pub fn history<const D: usize>(
+    input: &[(isize, isize)],
+    rule: Rule,
+    cycles: u32,
+) -> Vec<Generation<D>> {
+    let mut space = Space::<D>::new(input, rule);
+    let mut generations = vec![space.generation()];
+    for _ in 0..cycles {
+        space.step();
+        generations.push(space.generation());
+    }
+
+    generations
+}
+
+/// Renders every generation the way the puzzle description does.
+pub fn render_cycles<const D: usize>(input: &[(isize, isize)], rule: Rule, cycles: u32) -> String {
+    let mut space = Space::<D>::new(input, rule);
+    let mut out = format!("Before any cycles:\n\n{}\n", space.render());
+    for cycle in 1..=cycles {
+        space.step();
+        let plural = if cycle == 1 { "" } else { "s" };
+        out.push_str(&format!(
+            "\n\nAfter {} cycle{}:\n\n{}\n",
+            cycle,
+            plural,
+            space.render()
+        ));
+    }
+
+    out
+}
+
+type Input = Vec<(isize, isize)>;
 type Output = usize;
 
 fn input_transformer(input: &str) -> Input {
@@ -53,68 +340,34 @@ fn input_transformer(input: &str) -> Input {
                 .chars()
                 .enumerate()
                 .filter(|(x, c)| *c == '#')
-                .map(move |(x, _)| Coordinate(x as isize, y as isize, 0isize, 0isize))
+                .map(move |(x, _)| (x as isize, y as isize))
         })
         .collect()
 }
 
 fn solve_part1(input: Input) -> Output {
-    solve_space(input, Dimension::Three, 6)
+    solve_space::<3>(&input, 6)
 }
 
 fn solve_part2(input: Input) -> Output {
-    solve_space(input, Dimension::Four, 6)
+    solve_space::<4>(&input, 6)
 }
 
-fn solve_space(input: Input, dim: Dimension, cycles: u32) -> Output {
-    let mut active: HashSet<Coordinate> = input;
-
-    (0..cycles).for_each(|i| {
-        let snapshot = active.to_owned();
+fn solve_space<const D: usize>(input: &Input, cycles: u32) -> Output {
+    let mut space = Space::<D>::new(input, Rule::conway());
+    (0..cycles).for_each(|_| space.step());
 
-        // Rule #1
-        snapshot
-            .iter()
-            .filter(|c| {
-                let count = c
-                    .neighbors(&dim)
-                    .collect::<HashSet<_>>()
-                    .intersection(&snapshot)
-                    .count();
-
-                !(2 == count || 3 == count)
-            })
-            .for_each(|c| {
-                active.remove(c);
-            });
-
-        // Rule #2
-        snapshot
-            .iter()
-            .flat_map(|c| c.neighbors(&dim))
-            .filter(|c| !snapshot.contains(c))
-            .filter(|c| {
-                let count = c
-                    .neighbors(&dim)
-                    .collect::<HashSet<_>>()
-                    .intersection(&snapshot)
-                    .count();
-
-                count == 3
-            })
-            .unique()
-            .for_each(|c| {
-                active.insert(c);
-            });
-    });
-
-    active.len()
+    space.population()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{
+        history, input_transformer, neighbor_deltas, render_cycles, solve_part1, solve_part2,
+        solve_space, Coordinate, Rule, DAY,
+    };
     use crate::utils::*;
+    use itertools::Itertools;
 
     const SAMPLE: &str = ".#.
     ..#
@@ -136,7 +389,6 @@ mod tests {
     }
 
     #[test]
-    #[ignore = "too long"]
     fn test_part2_sample() {
         let solution = solve_part2(input_transformer(SAMPLE));
 
@@ -144,11 +396,107 @@ mod tests {
     }
 
     #[test]
-    #[ignore = "too long"]
     fn test_part2_puzzle() {
         let input = read_input(2020, DAY).unwrap();
         let solution = solve_part2(input_transformer(&input));
 
         assert_eq!(2280, solution);
     }
+
+    #[test]
+    fn test_neighbor_deltas() {
+        assert_eq!(8, neighbor_deltas::<2>().len());
+        assert_eq!(26, neighbor_deltas::<3>().len());
+        assert_eq!(80, neighbor_deltas::<4>().len());
+        assert_eq!(728, neighbor_deltas::<6>().len());
+    }
+
+    #[test]
+    fn test_multiplicity() {
+        assert_eq!(1, Coordinate([3, 4, 0, 0]).multiplicity());
+        assert_eq!(4, Coordinate([3, 4, 0, 2]).multiplicity());
+        assert_eq!(4, Coordinate([3, 4, 2, 2]).multiplicity());
+        assert_eq!(8, Coordinate([3, 4, 1, 2]).multiplicity());
+        assert_eq!(48, Coordinate([0, 0, 1, 2, 3]).multiplicity());
+        assert_eq!(
+            Coordinate([3, 4, 1, 2]),
+            Coordinate([3, 4, 2, -1]).canonical()
+        );
+    }
+
+    #[test]
+    fn test_higher_space() {
+        assert_eq!(5760, solve_space::<5>(&input_transformer(SAMPLE), 6));
+    }
+
+    #[test]
+    fn test_flat_space() {
+        // In 2D the sample is a glider, which keeps its 5 cells forever
+        assert_eq!(5, solve_space::<2>(&input_transformer(SAMPLE), 12));
+    }
+
+    #[test]
+    fn test_rule() {
+        let rule: Rule = "B36/S23".parse().unwrap();
+        assert_eq!(hashset![3, 6], rule.born);
+        assert_eq!("B36/S23", rule.to_string());
+        assert_eq!(Rule::conway(), "b3/s23".parse().unwrap());
+        assert_eq!(
+            "B3/S2,3,12",
+            "B3/S2,3,12".parse::<Rule>().unwrap().to_string()
+        );
+
+        assert!("B3S23".parse::<Rule>().is_err());
+        assert!("B3/Sx".parse::<Rule>().is_err());
+        assert!("B03/S23".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn test_render_cycles() {
+        let rendered = render_cycles::<3>(&input_transformer(SAMPLE), Rule::conway(), 1);
+
+        assert_eq!(
+            "Before any cycles:
+
+z=0
+.#.
+..#
+###
+
+
+After 1 cycle:
+
+z=-1
+#..
+..#
+.#.
+
+z=0
+#.#
+.##
+.#.
+
+z=1
+#..
+..#
+.#.
+",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let generations = history::<4>(&input_transformer(SAMPLE), Rule::conway(), 2);
+
+        assert_eq!(
+            vec![5, 29, 60],
+            generations.iter().map(|g| g.population).collect_vec()
+        );
+        assert_eq!(Some(([0, 0, 0, 0], [2, 2, 0, 0])), generations[0].bounds);
+        assert_eq!(Some(([0, 1, -1, -1], [2, 3, 1, 1])), generations[1].bounds);
+        // Without survivors only the cells born in the cycle are left
+        let generations = history::<2>(&input_transformer(SAMPLE), "B3/S".parse().unwrap(), 1);
+        assert_eq!(2, generations[1].population);
+    }
 }
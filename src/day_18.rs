@@ -1,54 +1,338 @@
+use std::fmt;
+use std::iter::Peekable;
+
 const DAY: u32 = 18;
 
 type Input = Vec<String>;
 type Output = u64;
 
+/// Byte range of an expression node within its source line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Mul,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(u64, Span),
+    Group(Box<Expr>, Span),
+    BinOp(Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn binary(op: Op, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::BinOp(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Num(_, span) | Expr::Group(_, span) => *span,
+            Expr::BinOp(_, lhs, rhs) => Span {
+                start: lhs.span().start,
+                end: rhs.span().end,
+            },
+        }
+    }
+
+    /// Evaluates the expression with checked arithmetic, on overflow returns
+    /// the span of the offending operation.
+    pub fn eval(&self) -> Result<u64, Span> {
+        match self {
+            Expr::Num(n, _) => Ok(*n),
+            Expr::Group(e, _) => e.eval(),
+            Expr::BinOp(op, lhs, rhs) => {
+                let (x, y) = (lhs.eval()?, rhs.eval()?);
+                match op {
+                    Op::Add => x.checked_add(y),
+                    Op::Mul => x.checked_mul(y),
+                }
+                .ok_or_else(|| self.span())
+            }
+        }
+    }
+
+    pub fn parenthesised(&self) -> String {
+        match self {
+            Expr::Num(n, _) => n.to_string(),
+            Expr::Group(e, _) => e.parenthesised(),
+            Expr::BinOp(op, lhs, rhs) => {
+                format!("({} {} {})", lhs.parenthesised(), op, rhs.parenthesised())
+            }
+        }
+    }
+
+    /// Applies the first operation, in evaluation order, whose operands are
+    /// both numbers, or drops the parentheses around a single number. Returns
+    /// `false` once the expression is a single number.
+    fn reduce(&mut self) -> Result<bool, Span> {
+        match self {
+            Expr::Num(_, _) => Ok(false),
+            Expr::Group(e, _) => {
+                let reduced = e.reduce()?;
+                if let Expr::Num(n, _) = **e {
+                    *self = Expr::Num(n, self.span());
+                    return Ok(true);
+                }
+                Ok(reduced)
+            }
+            Expr::BinOp(_, lhs, rhs) => {
+                if lhs.reduce()? || rhs.reduce()? {
+                    return Ok(true);
+                }
+                *self = Expr::Num(self.eval()?, self.span());
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Add => write!(f, "+"),
+            Op::Mul => write!(f, "*"),
+        }
+    }
+}
+
+/// Source form of the expression, keeping only the parentheses written in it.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Num(n, _) => write!(f, "{}", n),
+            Expr::Group(e, _) => write!(f, "({})", e),
+            Expr::BinOp(op, lhs, rhs) => write!(f, "{} {} {}", lhs, op, rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprErrorKind {
+    Parse(String),
+    Overflow,
+}
+
+/// Parse or evaluation error, pointing at the offending part of the source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    pub kind: ExprErrorKind,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    pub source: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExprErrorKind::Parse(expected) => write!(
+                f,
+                "line {}, column {}: expected {}",
+                self.line, self.column, expected
+            )?,
+            ExprErrorKind::Overflow => write!(
+                f,
+                "line {}, column {}: arithmetic overflow",
+                self.line, self.column
+            )?,
+        }
+        writeln!(f)?;
+        writeln!(f, "{}", self.source)?;
+        write!(
+            f,
+            "{}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.len.max(1))
+        )
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+/// Binding strength of the operators, higher binds tighter. All operators are
+/// left associative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precedence {
+    pub add: u8,
+    pub mul: u8,
+}
+
+pub const PART1: Precedence = Precedence { add: 0, mul: 0 };
+pub const PART2: Precedence = Precedence { add: 1, mul: 0 };
+
+impl Precedence {
+    fn binding(&self, op: Op) -> u8 {
+        match op {
+            Op::Add => self.add,
+            Op::Mul => self.mul,
+        }
+    }
+
+    fn build(&self, first: Expr, rest: Vec<(Op, Expr)>) -> Expr {
+        self.climb(first, &mut rest.into_iter().peekable(), 0)
+    }
+
+    // Precedence climbing over a flat `operand (op operand)*` chain
+    fn climb<I>(&self, mut lhs: Expr, rest: &mut Peekable<I>, min: u8) -> Expr
+    where
+        I: Iterator<Item = (Op, Expr)>,
+    {
+        while let Some(op) = rest.peek().map(|(op, _)| *op) {
+            if self.binding(op) < min {
+                break;
+            }
+            let (_, mut rhs) = rest.next().unwrap();
+            while let Some(next) = rest.peek().map(|(op, _)| *op) {
+                if self.binding(next) <= self.binding(op) {
+                    break;
+                }
+                rhs = self.climb(rhs, rest, self.binding(op) + 1);
+            }
+            lhs = Expr::binary(op, lhs, rhs);
+        }
+
+        lhs
+    }
+}
+
+const NUMBER_TOO_LARGE: &str = "number fitting in u64";
+
 peg::parser! {
     grammar math_parser() for str {
-        rule number() -> u64
-          = n:$(['0'..='9']+) { n.parse().unwrap() }
+        rule _() = quiet!{[' ' | '\t']*}
 
-        rule whitespace() = quiet!{[' ' | '\t']+}
+        rule number() -> Expr
+          = start:position!() n:$(['0'..='9']+) end:position!() {?
+              n.parse()
+                  .map(|n| Expr::Num(n, Span { start, end }))
+                  .or(Err(NUMBER_TOO_LARGE))
+          }
+
+        rule operator() -> Op
+          = "+" { Op::Add }
+          / "*" { Op::Mul }
+
+        rule operand(p: Precedence) -> Expr
+          = number()
+          / start:position!() "(" _ e:chain(p) _ ")" end:position!() {
+              Expr::Group(Box::new(e), Span { start, end })
+          }
+
+        rule chain(p: Precedence) -> Expr
+          = first:operand(p) rest:(_ op:operator() _ e:operand(p) { (op, e) })* {
+              p.build(first, rest)
+          }
+
+        pub rule expression(p: Precedence) -> Expr = _ e:chain(p) _ { e }
+    }
+}
 
-        pub rule expression_part1() -> u64 = precedence!{
-            x:(@) "+" y:@ { x + y }
-            x:(@) "*" y:@ { x * y }
-            n:number() { n }
-            "(" e:expression_part1() ")" { e }
+pub fn parse(line: usize, source: &str, precedence: Precedence) -> Result<Expr, ExprError> {
+    math_parser::expression(source, precedence).map_err(|e| {
+        let mut start = e.location.offset;
+        if e.expected.tokens().any(|t| t == NUMBER_TOO_LARGE) {
+            // The number is rejected after it was consumed, underline it backwards
+            start = source[..start]
+                .trim_end_matches(|c: char| c.is_ascii_digit())
+                .len();
         }
+        let end = start
+            + source[start..]
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
 
-        pub rule expression_part2() -> u64 = precedence!{
-            x:(@) "*" y:@ { x * y }
-            --
-            x:(@) "+" y:@ { x + y }
-            --
-            n:number() { n }
-            "(" e:expression_part2() ")" { e }
+        ExprError {
+            kind: ExprErrorKind::Parse(e.expected.to_string()),
+            line,
+            column: start + 1,
+            len: (end - start).max(1),
+            source: source.to_owned(),
         }
+    })
+}
+
+fn overflow(line: usize, source: &str, span: Span) -> ExprError {
+    ExprError {
+        kind: ExprErrorKind::Overflow,
+        line,
+        column: span.start + 1,
+        len: span.end - span.start,
+        source: source.to_owned(),
+    }
+}
+
+fn evaluate(line: usize, source: &str, precedence: Precedence) -> Result<u64, ExprError> {
+    parse(line, source, precedence)?
+        .eval()
+        .map_err(|span| overflow(line, source, span))
+}
+
+/// Fully parenthesised form of the expression, e.g. `2 * 3 + (4 * 5)` becomes
+/// `(2 * (3 + (4 * 5)))` under `PART2`.
+pub fn parenthesise(
+    line: usize,
+    source: &str,
+    precedence: Precedence,
+) -> Result<String, ExprError> {
+    parse(line, source, precedence).map(|e| e.parenthesised())
+}
+
+/// Reduces the expression one operation at a time in evaluation order and
+/// returns every intermediate form, starting with the source and ending with
+/// the result.
+pub fn trace(line: usize, source: &str, precedence: Precedence) -> Result<Vec<String>, ExprError> {
+    let mut expr = parse(line, source, precedence)?;
+    let mut steps = vec![expr.to_string()];
+    while expr.reduce().map_err(|span| overflow(line, source, span))? {
+        steps.push(expr.to_string());
     }
+
+    Ok(steps)
 }
 
 fn input_transformer(input: &str) -> Input {
-    input.lines().map(|l| l.replace(' ', "")).collect()
+    input.lines().map(|l| l.trim().to_owned()).collect()
 }
 
-fn solve_part1(input: &Input) -> Output {
+fn solve(input: &Input, precedence: Precedence) -> Result<Output, ExprError> {
     input
         .iter()
-        .filter_map(|ex| math_parser::expression_part1(ex).ok())
-        .sum()
+        .enumerate()
+        .filter(|(_, ex)| !ex.is_empty())
+        .try_fold(0u64, |acc, (i, ex)| {
+            let n = evaluate(i + 1, ex, precedence)?;
+            acc.checked_add(n).ok_or_else(|| ExprError {
+                kind: ExprErrorKind::Overflow,
+                line: i + 1,
+                column: 1,
+                len: ex.len(),
+                source: ex.to_owned(),
+            })
+        })
 }
 
-fn solve_part2(input: &Input) -> Output {
-    input
-        .iter()
-        .filter_map(|ex| math_parser::expression_part2(ex).ok())
-        .sum()
+fn solve_part1(input: &Input) -> Result<Output, ExprError> {
+    solve(input, PART1)
+}
+
+fn solve_part2(input: &Input) -> Result<Output, ExprError> {
+    solve(input, PART2)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{input_transformer, solve_part1, solve_part2, DAY};
+    use super::{
+        input_transformer, parenthesise, parse, solve_part1, solve_part2, trace, ExprErrorKind,
+        Precedence, DAY, PART1, PART2,
+    };
     use crate::utils::*;
 
     #[test]
@@ -57,7 +341,7 @@ mod tests {
         5 + (8 * 3 + 9 + 3 * 4 * 3)
         5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))
         ((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2";
-        let solution = solve_part1(&input_transformer(input));
+        let solution = solve_part1(&input_transformer(input)).unwrap();
 
         assert_eq!(26 + 437 + 12240 + 13632, solution);
     }
@@ -65,7 +349,7 @@ mod tests {
     #[test]
     fn test_part1_puzzle() {
         let input = read_input(2020, DAY).unwrap();
-        let solution = solve_part1(&input_transformer(&input));
+        let solution = solve_part1(&input_transformer(&input)).unwrap();
 
         assert_eq!(12918250417632, solution);
     }
@@ -77,7 +361,7 @@ mod tests {
         5 + (8 * 3 + 9 + 3 * 4 * 3)
         5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))
         ((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2";
-        let solution = solve_part2(&input_transformer(input));
+        let solution = solve_part2(&input_transformer(input)).unwrap();
 
         assert_eq!(51 + 46 + 1445 + 669060 + 23340, solution);
     }
@@ -85,8 +369,84 @@ mod tests {
     #[test]
     fn test_part2_puzzle() {
         let input = read_input(2020, DAY).unwrap();
-        let solution = solve_part2(&input_transformer(&input));
+        let solution = solve_part2(&input_transformer(&input)).unwrap();
 
         assert_eq!(171259538712010, solution);
     }
+
+    #[test]
+    fn test_parse_error() {
+        let input = "1 + 2
+        3 * (4 + ) * 5";
+        let error = solve_part1(&input_transformer(input)).unwrap_err();
+
+        assert_eq!(2, error.line);
+        assert_eq!(10, error.column);
+        assert_eq!(
+            "line 2, column 10: expected one of \"(\", '0'..='9'\n3 * (4 + ) * 5\n         ^",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_number_too_large() {
+        let error = parse(1, "2 + 123456789012345678901 * 3", PART2).unwrap_err();
+
+        assert_eq!(5, error.column);
+        assert_eq!(21, error.len);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let error =
+            solve_part2(&input_transformer("1 + (4294967296 * 4294967296) + 1")).unwrap_err();
+
+        assert_eq!(ExprErrorKind::Overflow, error.kind);
+        assert_eq!(
+            "line 1, column 6: arithmetic overflow\n1 + (4294967296 * 4294967296) + 1\n     ^^^^^^^^^^^^^^^^^^^^^^^",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_parenthesise() {
+        let source = "2 * 3 + (4 * 5)";
+
+        assert_eq!(
+            "((2 * 3) + (4 * 5))",
+            parenthesise(1, source, PART1).unwrap()
+        );
+        assert_eq!(
+            "(2 * (3 + (4 * 5)))",
+            parenthesise(1, source, PART2).unwrap()
+        );
+
+        let mul_first = Precedence { add: 0, mul: 1 };
+        assert_eq!(
+            "((1 + ((2 * 3) * 4)) + 5)",
+            parenthesise(1, "1 + 2 * 3 * 4 + 5", mul_first).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let steps = trace(1, "1 + (2 * 3) + (4 * (5 + 6))", PART2).unwrap();
+
+        assert_eq!(
+            vec![
+                "1 + (2 * 3) + (4 * (5 + 6))",
+                "1 + 6 + (4 * (5 + 6))",
+                "7 + (4 * (5 + 6))",
+                "7 + (4 * 11)",
+                "7 + 44",
+                "51",
+            ],
+            steps
+        );
+        assert_eq!(vec!["(5)", "5"], trace(1, "(5)", PART1).unwrap());
+        assert_eq!(
+            vec!["(2) * 3", "2 * 3", "6"],
+            trace(1, "(2) * 3", PART1).unwrap()
+        );
+    }
 }
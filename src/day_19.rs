@@ -1,3 +1,4 @@
+use crate::utils::*;
 use pest::Parser;
 
 const DAY: u32 = 19;
@@ -30,22 +31,12 @@ mod puzzle_2 {
 }
 
 fn input_transformer(input: &str) -> Input {
-    let init = (true, &mut Vec::<String>::new());
-    let expressions = input
-        .lines()
-        .map(|l| l.trim())
-        .fold(init, |(skip, expressions), l| {
-            if skip && l.is_empty() {
-                return (false, expressions);
-            } else if !skip {
-                expressions.push(l.to_owned())
-            }
-
-            (skip, expressions)
-        })
-        .1;
-
-    expressions.to_owned()
+    // The rules are compiled into the grammars, only the messages are read
+    paragraphs(input)
+        .nth(1)
+        .into_iter()
+        .flat_map(|p| p.lines().map(|(_, l)| l.to_owned()))
+        .collect()
 }
 
 fn solve_part1_sample(input: &Input) -> Output {
@@ -1,9 +1,12 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 #![allow(clippy::ptr_arg)]
-#![allow(clippy::clippy::needless_range_loop)]
+#![allow(clippy::needless_range_loop)]
 #![allow(clippy::many_single_char_names)]
-#![feature(str_split_once)]
+#![cfg_attr(test, feature(test))]
+
+#[cfg(test)]
+extern crate test;
 
 #[macro_use]
 extern crate lazy_static;
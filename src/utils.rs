@@ -47,6 +47,79 @@ pub fn modulo(x: isize, m: usize) -> usize {
     ((x % m + m) % m) as usize
 }
 
+/// A block of consecutive non-blank lines, trimmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Paragraph<'a> {
+    /// 1-based line number of the first line.
+    pub line: usize,
+    pub text: &'a str,
+}
+
+impl<'a> Paragraph<'a> {
+    /// Trimmed lines with their 1-based line numbers.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &'a str)> {
+        (self.line..).zip(self.text.lines().map(|l| l.trim()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Paragraphs<'a> {
+    input: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Iterator for Paragraphs<'a> {
+    type Item = Paragraph<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut start = None;
+        let mut end = self.pos;
+        while self.pos < self.input.len() {
+            let rest = &self.input[self.pos..];
+            let len = rest.find('\n').map_or(rest.len(), |i| i + 1);
+            let blank = rest[..len].trim().is_empty();
+            self.line += 1;
+            self.pos += len;
+            if !blank {
+                start.get_or_insert((self.pos - len, self.line));
+                end = self.pos;
+            } else if start.is_some() {
+                break;
+            }
+        }
+
+        start.map(|(start, line)| Paragraph {
+            line,
+            text: self.input[start..end].trim(),
+        })
+    }
+}
+
+/// Splits the input on blank lines, which may contain whitespace. Lines may
+/// end with `\n` or `\r\n`.
+pub fn paragraphs(input: &str) -> Paragraphs<'_> {
+    Paragraphs {
+        input,
+        pos: 0,
+        line: 0,
+    }
+}
+
+/// Lets `write` write to a temporary file named after `name`, then returns
+/// the file contents and removes it again.
+#[cfg(test)]
+pub fn read_written<T, E: Debug>(
+    name: &str,
+    write: impl FnOnce(&Path) -> std::result::Result<T, E>,
+) -> String {
+    let path = std::env::temp_dir().join(format!("aoc_{}_{}", std::process::id(), name));
+    let written = write(&path).map(|_| fs::read_to_string(&path));
+    let _ = fs::remove_file(&path);
+
+    written.unwrap().unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -58,4 +131,26 @@ mod tests {
         assert_eq!(8, modulo(-2, 10));
         assert_eq!(8, modulo(-12, 10));
     }
+
+    #[test]
+    fn test_paragraphs() {
+        let input = "\n  a b \r\n\tc\r\n \r\n\n  d\n   \n";
+        let found: Vec<_> = paragraphs(input).collect();
+
+        assert_eq!(
+            vec![
+                Paragraph {
+                    line: 2,
+                    text: "a b \r\n\tc"
+                },
+                Paragraph { line: 6, text: "d" },
+            ],
+            found
+        );
+        assert_eq!(
+            vec![(2, "a b"), (3, "c")],
+            found[0].lines().collect::<Vec<_>>()
+        );
+        assert_eq!(0, paragraphs(" \n\n").count());
+    }
 }